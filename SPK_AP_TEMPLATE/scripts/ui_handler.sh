@@ -15,24 +15,72 @@ load_config() {
     fi
 }
 
+# Keys the DSM UI may set; everything else in the request is ignored
+CONFIG_KEYS="DOWNLOAD_DIR PORT VERBOSE WORKERS SITE_LIMITS SITE_DELAYS MAX_RETRIES RETRY_BACKOFF
+DOWNLOAD_ARCHIVE DOWNLOAD_WINDOWS RATE_LIMIT CONCURRENT_FRAGMENTS DEFAULT_QUALITY DEFAULT_FORMAT
+INBOX_DIR SPACE_MARGIN LOW_SPACE ON_COLLISION FILENAME_UNICODE FILESYSTEM SUBTITLE_LANGUAGES
+SUBTITLE_MANUAL SUBTITLE_AUTOMATIC SUBTITLE_FORMAT SUBTITLE_MODE FILENAME_TEMPLATE FILENAME_TEMPLATES"
+
+# Check whether a key is one of CONFIG_KEYS
+is_config_key() {
+    for known in $CONFIG_KEYS; do
+        [ "$known" = "$1" ] && return 0
+    done
+    return 1
+}
+
+# Decode a form-urlencoded value ("+" and %XX)
+urldecode() {
+    LC_ALL=C awk -v s="$1" 'BEGIN {
+        gsub(/\+/, " ", s)
+        out = ""
+        while (match(s, /%[0-9A-Fa-f][0-9A-Fa-f]/)) {
+            hex = tolower(substr(s, RSTART + 1, 2))
+            code = (index("0123456789abcdef", substr(hex, 1, 1)) - 1) * 16 + index("0123456789abcdef", substr(hex, 2, 1)) - 1
+            out = out substr(s, 1, RSTART - 1) sprintf("%c", code)
+            s = substr(s, RSTART + 3)
+        }
+        printf "%s", out s
+    }'
+}
+
+# Quote a value for config.env, which is sourced by the scripts
+shell_quote() {
+    printf "'%s'" "$(printf '%s' "$1" | sed "s/'/'\\\\''/g")"
+}
+
 # Save configuration
 save_config() {
-    # Read POST data from DSM UI
+    # Keep the current values of keys the form doesn't send
+    load_config
+
+    # Read POST data from DSM UI (key=value&key=value) - only known keys are set
     read -r input
-    eval "$input"
+    while IFS= read -r pair; do
+        case "$pair" in
+            *=*) ;;
+            *) continue ;;
+        esac
+        key=$(printf '%s' "${pair%%=*}" | tr '[:lower:]' '[:upper:]')
+        if is_config_key "$key"; then
+            eval "$key=\$(urldecode \"\${pair#*=}\")"
+        fi
+    done << EOF
+$(printf '%s' "$input" | tr '&' '\n')
+EOF
 
     # Validate and save configuration
     DOWNLOAD_DIR="${DOWNLOAD_DIR:-/volume1/vda_serwer}"
     PORT="${PORT:-8081}"
     VERBOSE="${VERBOSE:-0}"
+    WORKERS="${WORKERS:-1}"
 
     # Create config file
     mkdir -p "$(dirname "$CONF_FILE")"
-    cat > "$CONF_FILE" << EOF
-DOWNLOAD_DIR="$DOWNLOAD_DIR"
-PORT="$PORT"
-VERBOSE="$VERBOSE"
-EOF
+    for key in $CONFIG_KEYS; do
+        eval "value=\$$key"
+        printf '%s=%s\n' "$key" "$(shell_quote "$value")"
+    done > "$CONF_FILE"
 
     echo "Configuration saved successfully"
 }
@@ -42,9 +90,9 @@ get_status() {
     load_config
     if [ -f "$START_STOP_SCRIPT" ]; then
         if "$START_STOP_SCRIPT" status > /dev/null 2>&1; then
-            echo "{\"status\":\"running\",\"port\":\"$PORT\",\"download_dir\":\"$DOWNLOAD_DIR\"}"
+            echo "{\"status\":\"running\",\"port\":\"$PORT\",\"download_dir\":\"$DOWNLOAD_DIR\",\"workers\":\"$WORKERS\"}"
         else
-            echo "{\"status\":\"stopped\",\"port\":\"$PORT\",\"download_dir\":\"$DOWNLOAD_DIR\"}"
+            echo "{\"status\":\"stopped\",\"port\":\"$PORT\",\"download_dir\":\"$DOWNLOAD_DIR\",\"workers\":\"$WORKERS\"}"
         fi
     else
         echo "{\"status\":\"error\",\"message\":\"Script not found\"}"
@@ -1,28 +1,11 @@
 import { showStatus } from './ui.js';
 
-export async function isUrlInQueue(vda, url) {
-    try {
-        const res = await fetch(`http://${vda.serverIp}:${vda.serverPort}/queue`);
-        const queue = await res.json();
-        return queue.some(item => item.url === url);
-    } catch (e) {
-        console.error('Failed to fetch queue:', e);
-        return false;
-    }
-}
-
 export async function downloadVideo(vda) {
     if (!vda.videoInfo) {
         showStatus(vda, vda.t('unknownError') || 'Unknown error', 'error');
         return;
     }
 
-    const alreadyInQueue = await isUrlInQueue(vda, vda.videoInfo.url);
-    if (alreadyInQueue) {
-        showStatus(vda, vda.t('alreadyInQueue') || 'Video is already in the queue', 'error');
-        return;
-    }
-
     const downloadBtn = document.getElementById('download-btn');
     const btnText = document.getElementById('download-btn-text');
     const spinner = document.getElementById('download-spinner');
@@ -57,6 +40,12 @@ export async function downloadVideo(vda) {
        const messageElement = document.getElementById('status-message-text');
        messageElement.textContent = `Dodano do kolejki pobierania id: ${data.id}`;
        document.getElementById('download-btn').style.display = 'none';
+     } else if (res.status === 409 && data.error_code === 'duplicate') {
+            // serwer wykrył ten sam film w kolejce albo w historii
+            showStatus(vda, vda.t('alreadyInQueue') || 'Video is already in the queue', 'error');
+            downloadBtn.disabled = false;
+            btnText.textContent = vda.t('downloadVideo') || 'Download Video';
+            spinner.style.display = 'none';
      } else {
             showStatus(vda, data.error || vda.t('downloadFailed') || 'Download Failed', 'error');
             downloadBtn.disabled = false;
@@ -1,14 +1,18 @@
 use std::fs;
-use std::path::{Component, Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
+use std::process::{Command, Stdio};
+use std::time::Duration;
 use actix_web::{web, HttpResponse, Responder};
-use tokio::sync::oneshot;
-use crate::{dodatkowe_funkcje, log_error, log_info, setup, AppState, DownloadJob, VerifyPremiumRequest, VerifyPremiumResponse, QUEUE_LEN};
+use futures_util::stream;
+use tokio::sync::broadcast::error::RecvError;
+use crate::dodatkowe_funkcje::queue_item_from_request;
+use crate::{disk, dodatkowe_funkcje, history, jobs, probe, subscriptions, log_error, log_info, setup, AppState, VerifyPremiumRequest, VerifyPremiumResponse};
 use crate::dodatkowe_funkcje::{downloads_folder, save_queue_to_file};
-use crate::models::{DownloadParams, DownloadQueueItem, DownloadRequest, DownloadResponse, JobResult, StatusResponse};
+use crate::pobieracz::{batch_entry, enqueue_download, enqueue_downloads, enqueue_playlist, expand_playlist, next_job_id, rate_limit_share, Enqueued};
+use crate::models::{BatchCancelResponse, BatchDownloadRequest, BatchEntryResult, BatchRequest, BatchResponse, DownloadQueueItem, DownloadRequest, DownloadResponse, ErrorCode, ErrorResponse, FilenameTemplatesResponse, FlatPlaylist, HistoryPruneQuery, RemovedResponse, HistoryQuery, JobState, ProbeInfo, ProbeRequest, ProbeResponse, RateLimitRequest, RateLimitResponse, RateValue, SubscriptionRequest, SubscriptionResponse, StatusResponse, SubtitlesResponse};
 
-pub(crate) async fn status_handler() -> impl Responder {
+pub(crate) async fn status_handler(app_state: web::Data<AppState>) -> impl Responder {
     let folder = downloads_folder();
     let version = option_env!("VDA_VERSION").unwrap_or(env!("CARGO_PKG_VERSION"));
     let resp = StatusResponse {
@@ -16,6 +20,7 @@ pub(crate) async fn status_handler() -> impl Responder {
         version,
         timestamp: dodatkowe_funkcje::current_unix_time_f64(),
         downloads_folder: folder,
+        disk: disk::status(&app_state),
     };
     HttpResponse::Ok().json(resp)
 }
@@ -29,8 +34,300 @@ pub(crate) async fn check_ytdlp_handler() -> impl Responder {
 
 
 pub(crate) async fn queue_handler(app_state: web::Data<AppState>) -> impl Responder {
-    let queue = app_state.queue.lock().unwrap();
-    HttpResponse::Ok().json(&*queue)
+    HttpResponse::Ok().json(jobs::queue_entries(&app_state))
+}
+
+pub(crate) async fn job_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+    match jobs::job_status(&app_state, id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().json(ErrorResponse {
+            success: false,
+            error: format!("Nie znaleziono zadania #{id}"),
+        }),
+    }
+}
+
+/// Anuluje zadanie - oczekujące usuwa z kolejki, a trwającemu przerywa yt-dlp/ffmpeg.
+pub(crate) async fn cancel_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+
+    if jobs::job_status(&app_state, id).is_none() {
+        return HttpResponse::NotFound().json(ErrorResponse {
+            success: false,
+            error: format!("Nie znaleziono zadania #{id}"),
+        });
+    }
+
+    if !jobs::set_job_state(&app_state, id, JobState::Cancelled) {
+        return HttpResponse::Conflict().json(ErrorResponse {
+            success: false,
+            error: format!("Zadanie #{id} jest już zakończone"),
+        });
+    }
+
+    {
+        let mut queue = app_state.queue.lock().unwrap();
+        queue.retain(|item| item.id != id);
+        save_queue_to_file(&queue);
+    }
+
+    log_info(&format!("🛑 Anulowano zadanie #{id}"));
+    HttpResponse::Ok().json(DownloadResponse {
+        success: true,
+        message: Some("Zadanie anulowane".into()),
+        error: None,
+        error_code: None,
+        output_path: None,
+        output_file: None,
+        id: Some(id),
+        duplicate_of: None,
+    })
+}
+
+/// Historia zakończonych pobrań ze stronicowaniem (`offset`, `limit`) i filtrami
+/// (`outcome`, `q`, `since`, `until`).
+pub(crate) async fn history_handler(query: web::Query<HistoryQuery>, app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(history::query(&app_state, &query))
+}
+
+pub(crate) async fn history_delete_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+    if !history::remove(&app_state, id) {
+        return HttpResponse::NotFound().json(ErrorResponse {
+            success: false,
+            error: format!("Brak zadania #{id} w historii"),
+        });
+    }
+    HttpResponse::Ok().json(RemovedResponse { success: true, removed: 1 })
+}
+
+/// Czyści historię według `before`/`outcome`, a całą tylko z `all=true`.
+pub(crate) async fn history_prune_handler(query: web::Query<HistoryPruneQuery>, app_state: web::Data<AppState>) -> impl Responder {
+    match history::prune(&app_state, &query) {
+        Some(removed) => HttpResponse::Ok().json(RemovedResponse { success: true, removed }),
+        None => HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "Podaj before, outcome albo all=true".into(),
+        }),
+    }
+}
+
+pub(crate) async fn subscriptions_handler(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(subscriptions::list(&app_state).into_iter().map(SubscriptionResponse::from).collect::<Vec<_>>())
+}
+
+fn subscription_not_found(id: u64) -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorResponse {
+        success: false,
+        error: format!("Nie znaleziono subskrypcji #{id}"),
+    })
+}
+
+pub(crate) async fn subscription_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+    match subscriptions::get(&app_state, id) {
+        Some(sub) => HttpResponse::Ok().json(SubscriptionResponse::from(sub)),
+        None => subscription_not_found(id),
+    }
+}
+
+pub(crate) async fn subscription_create_handler(body: web::Json<SubscriptionRequest>, app_state: web::Data<AppState>) -> impl Responder {
+    match subscriptions::create(&app_state, body.into_inner()) {
+        Ok(sub) => HttpResponse::Created().json(SubscriptionResponse::from(sub)),
+        Err(error) => HttpResponse::BadRequest().json(ErrorResponse { success: false, error }),
+    }
+}
+
+pub(crate) async fn subscription_update_handler(
+    path: web::Path<u64>,
+    body: web::Json<SubscriptionRequest>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match subscriptions::update(&app_state, id, body.into_inner()) {
+        Ok(Some(sub)) => HttpResponse::Ok().json(SubscriptionResponse::from(sub)),
+        Ok(None) => subscription_not_found(id),
+        Err(error) => HttpResponse::BadRequest().json(ErrorResponse { success: false, error }),
+    }
+}
+
+pub(crate) async fn subscription_delete_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+    if !subscriptions::remove(&app_state, id) {
+        return subscription_not_found(id);
+    }
+    HttpResponse::Ok().json(RemovedResponse { success: true, removed: 1 })
+}
+
+/// Sprawdza subskrypcję od razu, bez czekania na harmonogram.
+pub(crate) async fn subscription_check_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let id = path.into_inner();
+    match subscriptions::check(&app_state, id).await {
+        Some(sub) => HttpResponse::Ok().json(SubscriptionResponse::from(sub)),
+        None => subscription_not_found(id),
+    }
+}
+
+fn rate_limit_response(app_state: &AppState) -> RateLimitResponse {
+    let global = app_state.rate_limit.load(Ordering::SeqCst);
+    RateLimitResponse {
+        success: true,
+        rate_limit: (global > 0).then_some(global),
+        per_worker: rate_limit_share(app_state),
+        workers: app_state.config.workers,
+    }
+}
+
+pub(crate) async fn rate_limit_handler(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(rate_limit_response(&app_state))
+}
+
+/// Zmienia globalny limit prędkości bez restartu. Dotyczy pobrań, które wystartują później.
+pub(crate) async fn set_rate_limit_handler(body: web::Json<RateLimitRequest>, app_state: web::Data<AppState>) -> impl Responder {
+    let limit = match body.rate_limit.as_ref().map(RateValue::bytes).transpose() {
+        Ok(limit) => limit.flatten(),
+        Err(()) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                success: false,
+                error: "Nieprawidłowy limit prędkości (np. 500K, 2M)".into(),
+            });
+        }
+    };
+
+    app_state.rate_limit.store(limit.unwrap_or(0), Ordering::SeqCst);
+    match limit {
+        Some(rate) => log_info(&format!("🐢 Nowy globalny limit prędkości: {rate} B/s")),
+        None => log_info("🐇 Wyłączono globalny limit prędkości"),
+    }
+    HttpResponse::Ok().json(rate_limit_response(&app_state))
+}
+
+/// Strumień zdarzeń zadań (Server-Sent Events). Co 15 s wysyłany jest komentarz,
+/// żeby połączenie nie zostało zamknięte przez proxy.
+pub(crate) async fn events_handler(app_state: web::Data<AppState>) -> impl Responder {
+    let rx = app_state.events.subscribe();
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        let chunk = match tokio::time::timeout(Duration::from_secs(15), rx.recv()).await {
+            Ok(Ok(event)) => match serde_json::to_string(&event) {
+                Ok(json) => format!("data: {json}\n\n"),
+                Err(e) => {
+                    log_error(&format!("Błąd serializacji zdarzenia: {e}"));
+                    return None;
+                }
+            },
+            Ok(Err(RecvError::Lagged(skipped))) => {
+                log_error(&format!("⚠️ Klient SSE nie nadąża, pominięto {skipped} zdarzeń"));
+                format!(": pominięto {skipped} zdarzeń\n\n")
+            }
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => ": ping\n\n".to_string(),
+        };
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(chunk)), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+/// Metadane adresu (tytuł, autor, długość, miniatura, formaty) bez pobierania.
+pub(crate) async fn probe_handler(query: web::Query<ProbeRequest>) -> impl Responder {
+    probe_response(query.into_inner()).await
+}
+
+/// Jak `GET /probe`, ale dane logowania idą w treści żądania, a nie w adresie.
+pub(crate) async fn probe_post_handler(body: web::Json<ProbeRequest>) -> impl Responder {
+    probe_response(body.into_inner()).await
+}
+
+/// Sprawdza adres z żądania i odczytuje jego metadane (z pamięci podręcznej, jeśli są).
+async fn probe_request(req: ProbeRequest) -> Result<(ProbeInfo, bool), (ErrorCode, String)> {
+    let url = req.url.trim().to_string();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err((ErrorCode::UnsupportedUrl, "URL musi zaczynać się od http:// lub https://".into()));
+    }
+
+    let username = req.username.filter(|u| !u.is_empty());
+    let password = req.password.filter(|p| !p.is_empty());
+    probe::probe_cached(url, username, password).await
+}
+
+fn error_status(code: ErrorCode) -> actix_web::http::StatusCode {
+    actix_web::http::StatusCode::from_u16(code.http_status()).unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn probe_response(req: ProbeRequest) -> HttpResponse {
+    match probe_request(req).await {
+        Ok((info, cached)) => HttpResponse::Ok().json(ProbeResponse {
+            success: true,
+            cached,
+            error: None,
+            error_code: None,
+            info: Some(info),
+        }),
+        Err((code, msg)) => HttpResponse::build(error_status(code)).json(ProbeResponse {
+            success: false,
+            cached: false,
+            error: Some(msg),
+            error_code: Some(code),
+            info: None,
+        }),
+    }
+}
+
+/// Języki napisów dostępnych dla adresu - do wyboru w `subtitles.languages` przed dodaniem zadania.
+pub(crate) async fn subtitles_handler(query: web::Query<ProbeRequest>) -> impl Responder {
+    subtitles_response(query.into_inner()).await
+}
+
+/// Jak `GET /subtitles`, ale dane logowania idą w treści żądania.
+pub(crate) async fn subtitles_post_handler(body: web::Json<ProbeRequest>) -> impl Responder {
+    subtitles_response(body.into_inner()).await
+}
+
+async fn subtitles_response(req: ProbeRequest) -> HttpResponse {
+    match probe_request(req).await {
+        Ok((info, cached)) => {
+            let languages = |automatic: bool| {
+                info.subtitles
+                    .iter()
+                    .filter(|t| t.automatic == automatic)
+                    .map(|t| t.language.clone())
+                    .collect::<Vec<_>>()
+            };
+            HttpResponse::Ok().json(SubtitlesResponse {
+                success: true,
+                cached,
+                error: None,
+                error_code: None,
+                languages: languages(false),
+                automatic_languages: languages(true),
+                title: info.title,
+                tracks: info.subtitles,
+            })
+        }
+        Err((code, msg)) => HttpResponse::build(error_status(code)).json(SubtitlesResponse {
+            success: false,
+            cached: false,
+            error: Some(msg),
+            error_code: Some(code),
+            title: None,
+            languages: Vec::new(),
+            automatic_languages: Vec::new(),
+            tracks: Vec::new(),
+        }),
+    }
+}
+
+/// Nazwane szablony nazw plików do wyboru w `filename_template`.
+pub(crate) async fn filename_templates_handler(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(FilenameTemplatesResponse {
+        default: app_state.config.filename_template.clone(),
+        templates: app_state.config.filename_templates.clone(),
+    })
 }
 
 pub(crate) async fn verify_premium_handler(body: web::Json<VerifyPremiumRequest>) -> impl Responder {
@@ -116,41 +413,26 @@ pub(crate) async fn download_handler(
     body: web::Json<DownloadRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
-    let data = body.into_inner();
-
-    if data.url.trim().is_empty() {
-        return HttpResponse::BadRequest().json(DownloadResponse {
-            success: false,
-            message: None,
-            error: Some("URL jest wymagany".into()),
-            output_path: None,
-            id: None,
-        });
-    }
-
-    let url = data.url;
-    let quality = data.quality.unwrap_or_else(|| "best".into());
-    let format_selector = data.format.unwrap_or_else(|| "mp4".into());
-    let subfolder = data.subfolder.unwrap_or_default();
-    let custom_title = data.title;
-    let username = data.username;
-    let password = data.password;
-    let has_premium = username.is_some() && password.is_some();
+    let (queue_item, allow_duplicate) = match queue_item_from_request(body.into_inner(), &app_state.config) {
+        Ok(item) => item,
+        Err(msg) => return HttpResponse::BadRequest().json(DownloadResponse::error(msg)),
+    };
+    let has_premium = queue_item.username.is_some() && queue_item.password.is_some();
 
     log_info("📥 Otrzymano żądanie pobierania:");
-    log_info(&format!("   URL: {url}"));
-    log_info(&format!("   Jakość: {quality}"));
-    log_info(&format!("   Format: {format_selector}"));
+    log_info(&format!("   URL: {}", queue_item.url));
+    log_info(&format!("   Jakość: {}", queue_item.quality));
+    log_info(&format!("   Format: {}", queue_item.format_selector));
 
     if has_premium {
-        if let Some(u) = &username {
+        if let Some(u) = &queue_item.username {
             log_info(&format!("👑 Pobieranie Premium dla użytkownika: {u} (hasło: ****)"));
         }
     }
 
     let mut base_path = PathBuf::from(downloads_folder());
-    if !subfolder.is_empty() {
-        base_path.push(&subfolder);
+    if !queue_item.subfolder.is_empty() {
+        base_path.push(&queue_item.subfolder);
         log_info(&format!("📂 Używam podfolderu: {}", base_path.to_string_lossy()));
     }
     log_info(&format!(
@@ -161,83 +443,31 @@ pub(crate) async fn download_handler(
     if let Err(e) = fs::create_dir_all(&base_path) {
         let msg = format!("Nie udało się utworzyć folderu: {e}");
         log_error(&msg);
-        return HttpResponse::InternalServerError().json(DownloadResponse {
-            success: false,
-            message: None,
-            error: Some(msg),
-            output_path: None,
-            id: None,
-        });
-    }
-
-    let job_id = app_state
-        .job_counter
-        .fetch_add(1, Ordering::SeqCst)
-        .wrapping_add(1);
-
-    let params = DownloadParams {
-        url: url.clone(),
-        quality: quality.clone(),
-        format_selector: format_selector.clone(),
-        output_path: base_path.clone(),
-        custom_title: custom_title.clone(),
-        username: username.clone(),
-        password: password.clone(),
-    };
-
-    let queue_item = DownloadQueueItem {
-        id: job_id,
-        url,
-        quality,
-        format_selector,
-        subfolder,
-        title: custom_title,
-        username,
-        password,
-    };
-
-
-    {
-        let mut queue = app_state.queue.lock().unwrap();
-        queue.push(queue_item);
-        save_queue_to_file(&queue);
+        return HttpResponse::InternalServerError().json(DownloadResponse::error(msg));
     }
 
-    let (resp_tx, resp_rx) = oneshot::channel::<JobResult>();
-
-    let job = DownloadJob {
-        id: job_id,
-        params,
-        resp_tx,
+    let (job_id, duplicate_of, resp_rx) = match enqueue_download(&app_state, queue_item, allow_duplicate) {
+        Enqueued::Added { id, duplicate_of, result } => (id, duplicate_of, result),
+        Enqueued::Duplicate(existing) => return duplicate_response(existing),
     };
 
-    if let Err(e) = app_state.job_sender.send(job).await {
-        let msg = format!("Nie udało się dodać zadania do kolejki: {e}");
-        log_error(&msg);
-        return HttpResponse::InternalServerError().json(DownloadResponse {
-            success: false,
-            message: None,
-            error: Some("Nie udało się dodać zadania do kolejki".into()),
-            output_path: None,
-            id: None,
-        });
-    }
-
-    let queue_pos = QUEUE_LEN.fetch_add(1, Ordering::SeqCst) + 1;
+    let queue_pos = jobs::queue_position(&app_state, job_id).unwrap_or(0);
     log_info(&format!(
         "📥 Dodano pobieranie #{job_id} do kolejki (pozycja: {queue_pos})"
     ));
 
     match resp_rx.await {
         Ok(res) => {
-            QUEUE_LEN.fetch_sub(1, Ordering::SeqCst);
             if res.success {
                 HttpResponse::Ok().json(DownloadResponse {
                     success: true,
                     message: res.message,
                     error: None,
+                    error_code: None,
                     output_path: res.output_path,
+                    output_file: res.output_file,
                     id: Some(job_id),
+                    duplicate_of,
                 })
             } else {
                 let status = res.http_status;
@@ -246,22 +476,18 @@ pub(crate) async fn download_handler(
                         success: false,
                         message: res.message,
                         error: res.error,
+                        error_code: res.error_code,
                         output_path: res.output_path,
-                        id: None,
+                        output_file: res.output_file,
+                        id: Some(job_id),
+                        duplicate_of,
                     })
             }
         }
         Err(_) => {
-            QUEUE_LEN.fetch_sub(1, Ordering::SeqCst);
             let msg = "Błąd kolejki pobierania (kanał przerwany)".to_string();
             log_error(&msg);
-            HttpResponse::InternalServerError().json(DownloadResponse {
-                success: false,
-                message: None,
-                error: Some(msg),
-                output_path: None,
-                id: None,
-            })
+            HttpResponse::InternalServerError().json(DownloadResponse::error(msg))
         }
     }
 }
@@ -273,122 +499,255 @@ pub(crate) async fn download_handlerv2(
     app_state: web::Data<AppState>,
 ) -> impl Responder {
     let data = body.into_inner();
-
-    if data.url.trim().is_empty() {
-        return HttpResponse::BadRequest().json(DownloadResponse {
-            success: false,
-            message: None,
-            error: Some("URL jest wymagany".into()),
-            output_path: None,
-            id: None,
-        });
-    }
-
-    let url = data.url;
-    let quality = data.quality.unwrap_or_else(|| "best".into());
-    let format_selector = data.format.unwrap_or_else(|| "mp4".into());
-    let subfolder = data.subfolder.unwrap_or_default();
-    let custom_title = data.title;
-    let username = data.username;
-    let password = data.password;
+    let (expand_playlist, playlist_items) = (data.playlist, data.playlist_items.clone());
+    let (queue_item, allow_duplicate) = match queue_item_from_request(data, &app_state.config) {
+        Ok(item) => item,
+        Err(msg) => return HttpResponse::BadRequest().json(DownloadResponse::error(msg)),
+    };
 
     let mut base_path = PathBuf::from(downloads_folder());
-    if !subfolder.is_empty() {
-        let sub = Path::new(&subfolder);
-
-        if sub.is_absolute()
-            || sub.components().any(|c| matches!(c, Component::ParentDir))
-        {
-            let msg = "Nieprawidłowa nazwa podfolderu".to_string();
-            return HttpResponse::BadRequest().json(DownloadResponse {
-                success: false,
-                message: None,
-                error: Some(msg),
-                output_path: None,
-                id: None,
-            });
-        }
-
-        base_path.push(sub);
+    if !queue_item.subfolder.is_empty() {
+        base_path.push(&queue_item.subfolder);
     }
 
     if let Err(e) = fs::create_dir_all(&base_path) {
 
         let msg = format!("Nie udało się utworzyć folderu::{e:} /// {:?}", base_path);
         log_error(&format!("📂 Nie udało się utworzyć folderu:: {:?}", base_path));
-        return HttpResponse::InternalServerError().json(DownloadResponse {
-            success: false,
-            message: None,
-            error: Some(msg),
-            output_path: None,
-            id: None,
-        });
+        return HttpResponse::InternalServerError().json(DownloadResponse::error(msg));
 
     }
 
     log_info(&format!("📂 Folder gotowy: {:?}", base_path));
 
-    let job_id = app_state
-        .job_counter
-        .fetch_add(1, Ordering::SeqCst)
-        .wrapping_add(1);
-
-    let params = DownloadParams {
-        url: url.clone(),
-        quality: quality.clone(),
-        format_selector: format_selector.clone(),
-        output_path: base_path.clone(),
-        custom_title: custom_title.clone(),
-        username: username.clone(),
-        password: password.clone(),
+    if expand_playlist {
+        match load_playlist(&queue_item, playlist_items).await {
+            Ok(Some(playlist)) => {
+                return HttpResponse::Ok().json(enqueue_playlist(&app_state, queue_item, playlist, allow_duplicate));
+            }
+            Ok(None) => log_info("ℹ️ Adres nie jest playlistą - dodaję pojedyncze zadanie"),
+            Err((code, msg)) => {
+                return HttpResponse::build(actix_web::http::StatusCode::from_u16(code.http_status()).unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR))
+                    .json(DownloadResponse {
+                        error_code: Some(code),
+                        ..DownloadResponse::error(msg)
+                    });
+            }
+        }
+    }
+
+    // Dodajemy zadanie do kolejki w tle
+    let (job_id, duplicate_of) = match enqueue_download(&app_state, queue_item, allow_duplicate) {
+        Enqueued::Added { id, duplicate_of, .. } => (id, duplicate_of),
+        Enqueued::Duplicate(existing) => return duplicate_response(existing),
     };
+    probe::resolve_title_later(&app_state, job_id);
 
-    let title = custom_title.clone().unwrap_or_else(|| "Unknown Title".into());
-
-    let queue_item = DownloadQueueItem {
-        id: job_id,                   // unikalne ID zadania
-        url: url.clone(),             // adres URL wideo
-        quality: quality.clone(),     // wybrana jakość
-        format_selector: format_selector.clone(), // format wideo
-        subfolder: subfolder.clone(), // ewentualny podfolder w folderze pobierania
-        title: Some(title),           // tytuł wideo w polu `title`
-        username: username.clone(),   // opcjonalne dane premium
-        password: password.clone(),   // opcjonalne dane premium
+    // Od razu zwracamy odpowiedź do frontendu, że zadanie dodano
+    HttpResponse::Ok().json(DownloadResponse {
+        success: true,
+        message: Some("Dodano do kolejki".into()),
+        error: None,
+        error_code: None,
+        output_path: None,
+        output_file: None,
+        id: Some(job_id),
+        duplicate_of,
+    })
+}
+
+/// Dodaje wiele pobrań naraz. Najpierw sprawdza wszystkie wpisy - jeśli któryś jest błędny,
+/// nie dodaje niczego. Poprawne wpisy trafiają do kolejki jednym zapisem jako jedna partia.
+pub(crate) async fn batch_download_handler(body: web::Json<BatchRequest>, app_state: web::Data<AppState>) -> impl Responder {
+    let requests = match body.into_inner() {
+        BatchRequest::Entries(entries) => entries,
+        BatchRequest::WithDefaults(batch) => batch_requests(*batch),
     };
 
+    if requests.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "Partia nie zawiera żadnych adresów".into(),
+        });
+    }
 
-    {
-        let mut queue = app_state.queue.lock().unwrap();
-        queue.push(queue_item);
-        dodatkowe_funkcje::save_queue_to_file(&queue);
+    let validated: Vec<_> = requests
+        .into_iter()
+        .map(|req| (req.url.trim().to_string(), queue_item_from_request(req, &app_state.config)))
+        .collect();
+
+    if validated.iter().any(|(_, res)| res.is_err()) {
+        let entries = validated
+            .into_iter()
+            .map(|(url, res)| BatchEntryResult {
+                url,
+                id: None,
+                playlist_index: None,
+                error: res.err(),
+                error_code: None,
+                duplicate_of: None,
+            })
+            .collect();
+        return HttpResponse::BadRequest().json(BatchResponse {
+            success: false,
+            batch_id: None,
+            title: None,
+            added: 0,
+            entries,
+        });
     }
 
-    let (resp_tx, _resp_rx) = oneshot::channel::<JobResult>();
+    let batch_id = next_job_id(&app_state);
+    let mut items = Vec::with_capacity(validated.len());
+    for (_, res) in validated {
+        let Ok((mut item, allow_duplicate)) = res else {
+            continue;
+        };
 
-    let job = DownloadJob {
-        id: job_id,
-        params,
-        resp_tx,
-    };
+        let mut folder = PathBuf::from(downloads_folder());
+        if !item.subfolder.is_empty() {
+            folder.push(&item.subfolder);
+        }
+        if let Err(e) = fs::create_dir_all(&folder) {
+            let msg = format!("Nie udało się utworzyć folderu {:?}: {e}", folder);
+            log_error(&format!("📂 {msg}"));
+            return HttpResponse::InternalServerError().json(ErrorResponse { success: false, error: msg });
+        }
 
-    // Dodajemy zadanie do kolejki w tle
-    if let Err(e) = app_state.job_sender.send(job).await {
-        let msg = format!("Nie udało się dodać zadania do kolejki: {e}");
-        return HttpResponse::InternalServerError().json(DownloadResponse {
+        item.batch_id = Some(batch_id);
+        items.push((item, allow_duplicate));
+    }
+
+    let urls: Vec<String> = items.iter().map(|(item, _)| item.url.clone()).collect();
+    let entries: Vec<BatchEntryResult> = urls
+        .into_iter()
+        .zip(enqueue_downloads(&app_state, items))
+        .map(|(url, enqueued)| batch_entry(url, None, enqueued))
+        .collect();
+    for id in entries.iter().filter_map(|e| e.id) {
+        probe::resolve_title_later(&app_state, id);
+    }
+
+    let added = entries.iter().filter(|e| e.id.is_some()).count();
+    log_info(&format!("📥 Partia #{batch_id}: dodano {added} z {} adresów", entries.len()));
+
+    HttpResponse::Ok().json(BatchResponse {
+        success: added > 0,
+        batch_id: (added > 0).then_some(batch_id),
+        title: None,
+        added,
+        entries,
+    })
+}
+
+/// Rozwija listę adresów i uzupełnia wpisy wspólnymi ustawieniami partii.
+fn batch_requests(batch: BatchDownloadRequest) -> Vec<DownloadRequest> {
+    let listed = batch
+        .urls
+        .as_deref()
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|url| DownloadRequest {
+            url: url.to_string(),
+            ..Default::default()
+        });
+
+    batch
+        .entries
+        .into_iter()
+        .chain(listed.collect::<Vec<_>>())
+        .map(|req| DownloadRequest {
+            quality: req.quality.or_else(|| batch.quality.clone()),
+            format: req.format.or_else(|| batch.format.clone()),
+            subfolder: req.subfolder.or_else(|| batch.subfolder.clone()),
+            username: req.username.or_else(|| batch.username.clone()),
+            password: req.password.or_else(|| batch.password.clone()),
+            allow_duplicate: req.allow_duplicate || batch.allow_duplicate,
+            use_archive: req.use_archive.or(batch.use_archive),
+            start_after: req.start_after.or(batch.start_after),
+            limit_rate: req.limit_rate.or_else(|| batch.limit_rate.clone()),
+            filename_template: req.filename_template.or_else(|| batch.filename_template.clone()),
+            on_collision: req.on_collision.or(batch.on_collision),
+            subtitles: req.subtitles.or_else(|| batch.subtitles.clone()),
+            ..req
+        })
+        .collect()
+}
+
+/// Odczytuje pozycje playlisty. `None`, jeśli adres wskazuje pojedynczy film.
+async fn load_playlist(item: &DownloadQueueItem, range: Option<String>) -> Result<Option<FlatPlaylist>, (ErrorCode, String)> {
+    let url = item.url.clone();
+    let username = item.username.clone();
+    let password = item.password.clone();
+
+    let playlist = tokio::task::spawn_blocking(move || {
+        expand_playlist(&url, range.as_deref(), username.as_deref(), password.as_deref())
+    })
+    .await
+    .map_err(|e| (ErrorCode::DownloadFailed, format!("Błąd rozwijania playlisty: {e}")))??;
+
+    if playlist.kind.as_deref() != Some("playlist") {
+        return Ok(None);
+    }
+    if playlist.entries.iter().all(Option::is_none) {
+        return Err((ErrorCode::Unavailable, "Playlista nie zawiera żadnych pozycji z podanego zakresu".into()));
+    }
+    Ok(Some(playlist))
+}
+
+/// Anuluje wszystkie niezakończone zadania partii (playlisty).
+pub(crate) async fn cancel_batch_handler(path: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let batch_id = path.into_inner();
+
+    let members: Vec<u64> = app_state
+        .jobs
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|job| job.batch_id == Some(batch_id))
+        .map(|job| job.id)
+        .collect();
+
+    if members.is_empty() {
+        return HttpResponse::NotFound().json(ErrorResponse {
             success: false,
-            message: None,
-            error: Some("Nie udało się dodać zadania do kolejki".into()),
-            output_path: None,
-            id: None,
+            error: format!("Nie znaleziono partii #{batch_id}"),
         });
     }
 
-    // Od razu zwracamy odpowiedź do frontendu, że zadanie dodano
-    HttpResponse::Ok().json(DownloadResponse {
+    let mut cancelled: Vec<u64> = members
+        .into_iter()
+        .filter(|id| jobs::set_job_state(&app_state, *id, JobState::Cancelled))
+        .collect();
+    cancelled.sort_unstable();
+
+    if !cancelled.is_empty() {
+        let mut queue = app_state.queue.lock().unwrap();
+        queue.retain(|item| !cancelled.contains(&item.id));
+        save_queue_to_file(&queue);
+    }
+
+    log_info(&format!("🛑 Anulowano {} zadań partii #{batch_id}", cancelled.len()));
+    HttpResponse::Ok().json(BatchCancelResponse {
         success: true,
-        message: Some("Dodano do kolejki".into()),
-        error: None,
+        batch_id,
+        cancelled,
+    })
+}
+
+/// Odpowiedź 409 dla filmu, który już jest w kolejce albo został pobrany.
+fn duplicate_response(existing: u64) -> HttpResponse {
+    log_info(&format!("⚠️ Pominięto duplikat zadania #{existing}"));
+    HttpResponse::Conflict().json(DownloadResponse {
+        success: false,
+        message: None,
+        error: Some(format!("Ten film jest już w kolejce albo został pobrany (zadanie #{existing})")),
+        error_code: Some(ErrorCode::Duplicate),
         output_path: None,
-        id: Some(job_id),
+        output_file: None,
+        id: Some(existing),
+        duplicate_of: Some(existing),
     })
 }
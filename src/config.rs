@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use time::{OffsetDateTime, UtcOffset};
+use crate::filename_template;
+use crate::models::{CollisionPolicy, SubtitleFormat, SubtitleMode};
+use crate::sanitize::Filesystem;
+
+/// Przedział godzin, w którym wolno zaczynać pobieranie (minuty od północy).
+/// `start > end` oznacza okno przechodzące przez północ, np. `22:00-06:00`.
+#[derive(Clone, Copy)]
+pub(crate) struct DownloadWindow {
+    start: u32,
+    end: u32,
+}
+
+impl DownloadWindow {
+    fn contains(self, minute: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Ustawienia serwera. Wartości domyślne można nadpisać zmiennymi `VDA_*`,
+/// a te z kolei argumentami wiersza poleceń (DSM przekazuje je z `config.env`).
+pub(crate) struct ServerConfig {
+    /// liczba równoległych pobierań
+    pub(crate) workers: usize,
+    /// maksymalna liczba równoległych pobrań dla serwisu, np. `cda.pl=1`
+    pub(crate) site_limits: HashMap<String, usize>,
+    /// minimalny odstęp (w sekundach) między startami pobrań z jednego serwisu
+    pub(crate) site_delays: HashMap<String, u64>,
+    /// ile razy ponowić zadanie po przejściowym błędzie
+    pub(crate) max_retries: u32,
+    /// opóźnienie pierwszej ponownej próby w sekundach, każda kolejna czeka dwa razy dłużej
+    pub(crate) retry_backoff: u64,
+    /// czy przekazywać yt-dlp `--download-archive`, żeby pomijał już pobrane filmy
+    pub(crate) download_archive: bool,
+    /// godziny, w których wolno zaczynać pobieranie (pusta lista - zawsze)
+    pub(crate) download_windows: Vec<DownloadWindow>,
+    /// strefa czasowa okien pobierania, odczytana przy starcie serwera
+    pub(crate) local_offset: UtcOffset,
+    /// łączny limit prędkości pobierania w B/s, dzielony między workery
+    /// (startowa wartość - można ją zmienić w trakcie przez `/admin/rate-limit`)
+    pub(crate) rate_limit: Option<u64>,
+    /// liczba równolegle pobieranych fragmentów (`--concurrent-fragments`)
+    pub(crate) concurrent_fragments: u32,
+    /// jakość i format dla pobrań, które ich nie podają (np. z folderu inbox)
+    pub(crate) default_quality: String,
+    pub(crate) default_format: String,
+    /// obserwowany folder, z którego pliki `.txt`/`.url`/`.webloc` trafiają do kolejki
+    pub(crate) inbox_dir: Option<PathBuf>,
+    /// zapas wolnego miejsca (w bajtach) ponad szacowany rozmiar filmu, wymagany przed startem
+    pub(crate) space_margin: u64,
+    /// poniżej tylu wolnych bajtów kolejka wstrzymuje się sama (`0` - wyłączone)
+    pub(crate) low_space: u64,
+    /// nazwane szablony nazw plików (wbudowane i z `VDA_FILENAME_TEMPLATES`)
+    pub(crate) filename_templates: BTreeMap<String, String>,
+    /// domyślny szablon nazwy pliku - nazwa z `filename_templates` albo sam szablon
+    pub(crate) filename_template: String,
+    /// co zrobić, gdy plik o docelowej nazwie już istnieje
+    pub(crate) on_collision: CollisionPolicy,
+    /// czy własne tytuły mogą zawierać litery spoza ASCII (domyślnie są transliterowane;
+    /// nazwy nadane przez yt-dlp zachowują oryginalne litery)
+    pub(crate) filename_unicode: bool,
+    /// system plików folderu pobierania - limit długości nazw i obsługa emoji
+    pub(crate) filesystem: Filesystem,
+    /// domyślne języki napisów, np. `pl,en` (pusta lista - bez napisów)
+    pub(crate) subtitle_languages: Vec<String>,
+    /// pobierać napisy dodane przez autora / generowane automatycznie
+    pub(crate) subtitle_manual: bool,
+    pub(crate) subtitle_automatic: bool,
+    pub(crate) subtitle_format: SubtitleFormat,
+    pub(crate) subtitle_mode: SubtitleMode,
+}
+
+impl ServerConfig {
+    pub(crate) fn from_env() -> Self {
+        ServerConfig {
+            workers: env_parse("VDA_WORKERS").unwrap_or(1).max(1),
+            site_limits: env::var("VDA_SITE_LIMITS").map(|v| parse_site_map(&v)).unwrap_or_default(),
+            site_delays: env::var("VDA_SITE_DELAYS").map(|v| parse_site_map(&v)).unwrap_or_default(),
+            max_retries: env_parse("VDA_MAX_RETRIES").unwrap_or(3),
+            retry_backoff: env_parse("VDA_RETRY_BACKOFF").unwrap_or(30),
+            download_archive: env::var("VDA_DOWNLOAD_ARCHIVE").ok().and_then(|v| parse_bool(&v)).unwrap_or(false),
+            download_windows: env::var("VDA_DOWNLOAD_WINDOWS").map(|v| parse_windows(&v)).unwrap_or_default(),
+            local_offset: UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC),
+            rate_limit: env::var("VDA_RATE_LIMIT").ok().and_then(|v| parse_rate(&v)),
+            concurrent_fragments: env_parse("VDA_CONCURRENT_FRAGMENTS").unwrap_or(10).max(1),
+            default_quality: non_empty_env("VDA_DEFAULT_QUALITY").unwrap_or_else(|| "best".into()),
+            default_format: non_empty_env("VDA_DEFAULT_FORMAT").unwrap_or_else(|| "mp4".into()),
+            inbox_dir: non_empty_env("VDA_INBOX_DIR").map(PathBuf::from),
+            space_margin: env::var("VDA_SPACE_MARGIN").ok().and_then(|v| parse_size(&v)).unwrap_or(512 << 20),
+            low_space: env::var("VDA_LOW_SPACE").ok().and_then(|v| parse_size(&v)).unwrap_or(1 << 30),
+            filename_templates: filename_template::builtin_templates()
+                .into_iter()
+                .chain(env::var("VDA_FILENAME_TEMPLATES").map(|v| parse_templates(&v)).unwrap_or_default())
+                .collect(),
+            filename_template: non_empty_env("VDA_FILENAME_TEMPLATE")
+                .unwrap_or_else(|| filename_template::DEFAULT_TEMPLATE.into()),
+            on_collision: env::var("VDA_ON_COLLISION")
+                .ok()
+                .and_then(|v| CollisionPolicy::parse(&v))
+                .unwrap_or(CollisionPolicy::Rename),
+            filename_unicode: env::var("VDA_FILENAME_UNICODE").ok().and_then(|v| parse_bool(&v)).unwrap_or(false),
+            filesystem: env::var("VDA_FILESYSTEM")
+                .ok()
+                .and_then(|v| Filesystem::parse(&v))
+                .unwrap_or(Filesystem::Native),
+            subtitle_languages: env::var("VDA_SUBTITLE_LANGUAGES").map(|v| parse_list(&v)).unwrap_or_default(),
+            subtitle_manual: env::var("VDA_SUBTITLE_MANUAL").ok().and_then(|v| parse_bool(&v)).unwrap_or(true),
+            subtitle_automatic: env::var("VDA_SUBTITLE_AUTOMATIC").ok().and_then(|v| parse_bool(&v)).unwrap_or(false),
+            subtitle_format: env::var("VDA_SUBTITLE_FORMAT")
+                .ok()
+                .and_then(|v| SubtitleFormat::parse(&v))
+                .unwrap_or(SubtitleFormat::Srt),
+            subtitle_mode: env::var("VDA_SUBTITLE_MODE")
+                .ok()
+                .and_then(|v| SubtitleMode::parse(&v))
+                .unwrap_or(SubtitleMode::Embed),
+        }
+    }
+
+    /// Ustawia opcję przekazaną jako `--nazwa wartość`. Zwraca `false` dla nieznanej opcji.
+    pub(crate) fn apply_cli_arg(&mut self, flag: &str, value: &str) -> bool {
+        match flag {
+            "--workers" => {
+                if let Ok(n) = value.trim().parse::<usize>() {
+                    self.workers = n.max(1);
+                }
+            }
+            "--site-limits" => self.site_limits = parse_site_map(value),
+            "--site-delays" => self.site_delays = parse_site_map(value),
+            "--max-retries" => {
+                if let Ok(n) = value.trim().parse() {
+                    self.max_retries = n;
+                }
+            }
+            "--retry-backoff" => {
+                if let Ok(secs) = value.trim().parse() {
+                    self.retry_backoff = secs;
+                }
+            }
+            "--download-archive" => {
+                if let Some(enabled) = parse_bool(value) {
+                    self.download_archive = enabled;
+                }
+            }
+            "--download-windows" => self.download_windows = parse_windows(value),
+            "--rate-limit" => self.rate_limit = parse_rate(value),
+            "--concurrent-fragments" => {
+                if let Ok(n) = value.trim().parse::<u32>() {
+                    self.concurrent_fragments = n.max(1);
+                }
+            }
+            "--default-quality" => self.default_quality = value.trim().to_string(),
+            "--default-format" => self.default_format = value.trim().to_string(),
+            "--inbox-dir" => self.inbox_dir = Some(value.trim()).filter(|v| !v.is_empty()).map(PathBuf::from),
+            "--space-margin" => {
+                if let Some(bytes) = parse_size(value) {
+                    self.space_margin = bytes;
+                }
+            }
+            "--low-space" => {
+                if let Some(bytes) = parse_size(value) {
+                    self.low_space = bytes;
+                }
+            }
+            "--filename-templates" => self.filename_templates.extend(parse_templates(value)),
+            "--filename-template" => self.filename_template = value.trim().to_string(),
+            "--on-collision" => {
+                if let Some(policy) = CollisionPolicy::parse(value) {
+                    self.on_collision = policy;
+                }
+            }
+            "--filename-unicode" => {
+                if let Some(enabled) = parse_bool(value) {
+                    self.filename_unicode = enabled;
+                }
+            }
+            "--filesystem" => {
+                if let Some(filesystem) = Filesystem::parse(value) {
+                    self.filesystem = filesystem;
+                }
+            }
+            "--subtitle-languages" => self.subtitle_languages = parse_list(value),
+            "--subtitle-manual" => {
+                if let Some(enabled) = parse_bool(value) {
+                    self.subtitle_manual = enabled;
+                }
+            }
+            "--subtitle-automatic" => {
+                if let Some(enabled) = parse_bool(value) {
+                    self.subtitle_automatic = enabled;
+                }
+            }
+            "--subtitle-format" => {
+                if let Some(format) = SubtitleFormat::parse(value) {
+                    self.subtitle_format = format;
+                }
+            }
+            "--subtitle-mode" => {
+                if let Some(mode) = SubtitleMode::parse(value) {
+                    self.subtitle_mode = mode;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Opóźnienie przed ponowieniem po `attempt` nieudanych próbach (maks. godzina).
+    pub(crate) fn retry_delay(&self, attempt: u32) -> u64 {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        self.retry_backoff.saturating_mul(factor).min(3600)
+    }
+
+    /// Sekunda doby czasu lokalnego dla czasu uniksowego `now`.
+    fn local_second_of_day(&self, now: f64) -> u32 {
+        let t = OffsetDateTime::from_unix_timestamp(now as i64)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .to_offset(self.local_offset);
+        u32::from(t.hour()) * 3600 + u32::from(t.minute()) * 60 + u32::from(t.second())
+    }
+
+    /// Czy w chwili `now` wolno zaczynać pobieranie.
+    pub(crate) fn in_download_window(&self, now: f64) -> bool {
+        let minute = self.local_second_of_day(now) / 60;
+        self.download_windows.is_empty() || self.download_windows.iter().any(|w| w.contains(minute))
+    }
+
+    /// Czas uniksowy najbliższego otwarcia okna pobierania.
+    pub(crate) fn next_window_start(&self, now: f64) -> Option<f64> {
+        let second = self.local_second_of_day(now);
+        self.download_windows
+            .iter()
+            .map(|w| {
+                let start = w.start * 60;
+                let wait = if start > second { start - second } else { 86_400 - second + start };
+                now.floor() + f64::from(wait)
+            })
+            .min_by(f64::total_cmp)
+    }
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Lista rozdzielona przecinkami, np. `pl, en` - bez pustych pozycji.
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+}
+
+/// `1`/`true`/`yes`/`on` albo `0`/`false`/`no`/`off`.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Prędkość w B/s: liczba bajtów albo z przyrostkiem `K`/`M`/`G` (jak w yt-dlp, np. `2.5M`).
+/// `0` i puste wartości oznaczają brak limitu.
+pub(crate) fn parse_rate(value: &str) -> Option<u64> {
+    let value = value.trim().trim_end_matches(['B', 'b']);
+    let (number, unit) = match value.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&value[..i], c.to_ascii_uppercase()),
+        _ => (value, ' '),
+    };
+    let multiplier = match unit {
+        ' ' => 1.0,
+        'K' => 1024.0,
+        'M' => 1024.0 * 1024.0,
+        'G' => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let rate = number.trim().parse::<f64>().ok().filter(|n| n.is_finite() && *n > 0.0)?;
+    Some((rate * multiplier) as u64).filter(|r| *r > 0)
+}
+
+/// Rozmiar w bajtach w tej samej składni co prędkość (`500M`, `2G`); `0` jest dozwolone.
+fn parse_size(value: &str) -> Option<u64> {
+    match value.trim() {
+        "0" => Some(0),
+        v => parse_rate(v),
+    }
+}
+
+/// `GG:MM` -> minuty od północy (`24:00` oznacza koniec doby).
+fn parse_hhmm(value: &str) -> Option<u32> {
+    let (h, m) = value.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+    (m < 60 && (h < 24 || (h == 24 && m == 0))).then_some(h * 60 + m)
+}
+
+/// Parsuje okna pobierania `01:00-07:00,22:00-23:30`. Błędne wpisy są pomijane.
+fn parse_windows(value: &str) -> Vec<DownloadWindow> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (start, end) = entry.split_once('-')?;
+            let (start, end) = (parse_hhmm(start)?, parse_hhmm(end)?);
+            (start != end).then_some(DownloadWindow { start: start % 1440, end })
+        })
+        .collect()
+}
+
+/// Parsuje nazwane szablony `nazwa=szablon;nazwa2=szablon2` (średnik, bo szablony mogą zawierać przecinki).
+fn parse_templates(value: &str) -> Vec<(String, String)> {
+    value
+        .split(';')
+        .filter_map(|entry| {
+            let (name, template) = entry.split_once('=')?;
+            let (name, template) = (name.trim().to_lowercase(), template.trim());
+            (!name.is_empty() && !template.is_empty()).then(|| (name, template.to_string()))
+        })
+        .collect()
+}
+
+/// Parsuje listę `serwis=wartość` rozdzieloną przecinkami, np. `cda.pl=1,youtube.com=3`.
+/// Błędne wpisy są pomijane.
+fn parse_site_map<T: FromStr>(value: &str) -> HashMap<String, T> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (site, v) = entry.split_once('=')?;
+            let site = site.trim().trim_start_matches("www.").to_lowercase();
+            if site.is_empty() {
+                return None;
+            }
+            Some((site, v.trim().parse().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use actix_web::web;
+use crate::dodatkowe_funkcje::downloads_folder;
+use crate::models::{DiskSpace, DownloadParams, ErrorCode, JobResult};
+use crate::{log_error, log_info, probe, AppState};
+
+/// Co ile sekund sprawdzane jest wolne miejsce, gdy nic się nie pobiera.
+const CHECK_SECS: u64 = 30;
+
+/// Powyżej tylu wolnych bajtów ponad zapas rozmiar filmu nie jest sprawdzany sondą.
+const PLENTY_OF_SPACE: u64 = 50 << 30;
+
+/// Rezerwacja dla filmu, którego rozmiaru nie sprawdzano (wolnego miejsca jest pod dostatkiem).
+const ASSUMED_SIZE: u64 = 4 << 30;
+
+/// Wolne i całkowite miejsce (w bajtach) na wolumenie ze ścieżką. Jeśli ścieżka
+/// jeszcze nie istnieje, sprawdzany jest najbliższy istniejący folder nadrzędny.
+pub(crate) fn space(path: &Path) -> Option<(u64, u64)> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    match fs4::statvfs(existing) {
+        Ok(stats) => Some((stats.available_space(), stats.total_space())),
+        Err(e) => {
+            log_error(&format!("💾 Nie udało się odczytać wolnego miejsca dla {:?}: {e}", existing));
+            None
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+    } else {
+        format!("{:.0} MiB", bytes as f64 / (1u64 << 20) as f64)
+    }
+}
+
+/// Stan dysku z folderem pobierania dla `/status`.
+pub(crate) fn status(app_state: &AppState) -> Option<DiskSpace> {
+    let (free, total) = space(Path::new(&downloads_folder()))?;
+    Some(DiskSpace {
+        free_bytes: free,
+        total_bytes: total,
+        paused: app_state.low_space.load(Ordering::SeqCst),
+        low_space_threshold: app_state.config.low_space,
+    })
+}
+
+/// Wstrzymuje kolejkę, gdy wolnego miejsca jest mniej niż `low_space`,
+/// i wznawia ją, gdy znów go wystarcza.
+pub(crate) fn refresh_pause(app_state: &AppState) {
+    let threshold = app_state.config.low_space;
+    let low = threshold > 0
+        && space(Path::new(&downloads_folder())).is_some_and(|(free, _)| free < threshold);
+
+    let was_low = app_state.low_space.swap(low, Ordering::SeqCst);
+    if low && !was_low {
+        log_error(&format!(
+            "⏸️ Mniej niż {} wolnego miejsca - kolejka wstrzymana",
+            format_size(threshold)
+        ));
+    } else if !low && was_low {
+        log_info("▶️ Wolne miejsce na dysku wróciło - wznawiam kolejkę");
+        app_state.dispatch.notify_waiters();
+    }
+}
+
+/// Pętla w tle - pilnuje progu wolnego miejsca.
+pub(crate) async fn monitor_loop(app_state: web::Data<AppState>) {
+    loop {
+        refresh_pause(&app_state);
+        tokio::time::sleep(Duration::from_secs(CHECK_SECS)).await;
+    }
+}
+
+/// Ile miejsca zajmują rezerwacje trwających pobrań.
+fn reserved(app_state: &AppState) -> u64 {
+    app_state.reserved_space.lock().unwrap().values().sum()
+}
+
+/// Zwalnia rezerwację miejsca zadania (po zakończeniu albo przed ponowieniem).
+pub(crate) fn release(app_state: &AppState, id: u64) {
+    app_state.reserved_space.lock().unwrap().remove(&id);
+}
+
+/// Szacowany rozmiar filmu: z sondy, która już jest w pamięci podręcznej (np. po uzupełnieniu
+/// tytułu), a jeśli jej nie ma - z nowej sondy. Przy dużym zapasie wolnego miejsca nowa sonda
+/// (dodatkowe zapytanie do serwisu) jest pomijana i przyjmowany jest `ASSUMED_SIZE`.
+async fn estimate_size(params: &DownloadParams, available: u64, margin: u64) -> u64 {
+    if let Some(info) = probe::cached(&params.url, params.username.as_deref(), params.password.as_deref()) {
+        return info.estimated_size.unwrap_or(0);
+    }
+    if available >= margin.saturating_add(PLENTY_OF_SPACE) {
+        return ASSUMED_SIZE;
+    }
+    // bez szacunku rozmiaru sprawdzany jest tylko sam zapas
+    match probe::probe_cached(params.url.clone(), params.username.clone(), params.password.clone()).await {
+        Ok((info, _)) => info.estimated_size.unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
+/// Sprawdza przed startem zadania, czy na docelowym wolumenie zmieści się film razem
+/// z zapasem `space_margin` i miejscem zarezerwowanym przez trwające pobrania,
+/// i rezerwuje miejsce dla zadania (zwalniane w `jobs::finish_job`).
+pub(crate) async fn ensure_space(app_state: &AppState, id: u64, params: &DownloadParams) -> Result<(), JobResult> {
+    let Some((free, _)) = space(&params.output_path) else {
+        return Ok(());
+    };
+    let margin = app_state.config.space_margin;
+    let estimate = estimate_size(params, free.saturating_sub(reserved(app_state)), margin).await;
+
+    // sprawdzenie i rezerwacja pod jedną blokadą - równolegle startujące workery
+    // nie mogą obu uznać tego samego miejsca za wolne
+    let free = space(&params.output_path).map_or(free, |(free, _)| free);
+    let mut reservations = app_state.reserved_space.lock().unwrap();
+    let reserved: u64 = reservations.values().sum();
+    let available = free.saturating_sub(reserved);
+    let required = estimate.saturating_add(margin);
+    if available >= required {
+        reservations.insert(id, estimate);
+        return Ok(());
+    }
+    drop(reservations);
+
+    let msg = format!(
+        "Za mało miejsca na dysku: wolne {} (w tym {} zarezerwowane dla trwających pobrań), potrzebne ok. {} (film {} + zapas {})",
+        format_size(free),
+        format_size(reserved),
+        format_size(required),
+        format_size(estimate),
+        format_size(margin)
+    );
+    log_error(&format!("💾 {msg}"));
+    Err(JobResult::failed(ErrorCode::InsufficientSpace, msg))
+}
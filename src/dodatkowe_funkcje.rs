@@ -1,9 +1,11 @@
 use std::env;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Mutex;
 use once_cell::sync::Lazy;
 use time::OffsetDateTime;
-use crate::models::DownloadQueueItem;
+use crate::config::ServerConfig;
+use crate::models::{DownloadParams, DownloadProgress, DownloadRequest, ErrorCode, DownloadQueueItem, JobState, RateValue, YtDlpEvent, UNKNOWN_TITLE};
+use crate::{filename_template, subtitles};
 use crate::{GLOBAL_DOWNLOAD_DIR};
 use crate::setup::is_synology;
 
@@ -16,6 +18,19 @@ static QUEUE_FILE_PATH: Lazy<Mutex<PathBuf>> = Lazy::new(|| {
     Mutex::new(path)
 });
 
+/// Katalog danych pakietu na Synology (obok pliku kolejki).
+static SYNOLOGY_VAR_DIR: &str = "/var/packages/vda_serwer/var";
+
+/// Ścieżka pliku danych serwera - na Synology w katalogu pakietu, w innych systemach
+/// w katalogu roboczym, tak samo jak plik kolejki.
+pub(crate) fn data_file_path(name: &str) -> PathBuf {
+    if is_synology() {
+        Path::new(SYNOLOGY_VAR_DIR).join(name)
+    } else {
+        PathBuf::from(name)
+    }
+}
+
 pub(crate) fn current_unix_time_f64() -> f64 {
     use std::time::{SystemTime, UNIX_EPOCH};
     let now = SystemTime::now()
@@ -47,17 +62,224 @@ pub(crate) fn downloads_folder() -> String {
     "Downloads".to_string()
 }
 
+/// Podfolder musi być ścieżką względną, która nie wychodzi poza folder pobierania.
+pub(crate) fn is_safe_subfolder(subfolder: &str) -> bool {
+    let sub = Path::new(subfolder);
+    !sub.is_absolute() && !sub.components().any(|c| matches!(c, Component::ParentDir))
+}
 
-pub(crate) fn parse_ytdlp_output_line(line: &str, downloaded_file: &mut Option<PathBuf>) {
+/// Sprawdza żądanie pobrania (pojedyncze albo wpis partii) i zamienia je na element kolejki
+/// (ID nadaje `enqueue_download`). Zwraca też `allow_duplicate`.
+pub(crate) fn queue_item_from_request(req: DownloadRequest, config: &ServerConfig) -> Result<(DownloadQueueItem, bool), String> {
+    let url = req.url.trim().to_string();
+    if url.is_empty() {
+        return Err("URL jest wymagany".into());
+    }
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err("URL musi zaczynać się od http:// lub https://".into());
+    }
+
+    let subfolder = req.subfolder.unwrap_or_default();
+    if !subfolder.is_empty() && !is_safe_subfolder(&subfolder) {
+        return Err("Nieprawidłowa nazwa podfolderu".into());
+    }
+
+    let limit_rate = req
+        .limit_rate
+        .as_ref()
+        .map(RateValue::bytes)
+        .transpose()
+        .map_err(|()| "Nieprawidłowy limit prędkości (np. 500K, 2M)".to_string())?
+        .flatten();
+    let filename_template = req
+        .filename_template
+        .as_deref()
+        .map(|t| filename_template::resolve(config, Some(t)))
+        .transpose()?;
+    if let Some(options) = &req.subtitles {
+        subtitles::validate(options)?;
+    }
+
+    let item = DownloadQueueItem {
+        id: 0,
+        url,
+        quality: req.quality.unwrap_or_else(|| config.default_quality.clone()),
+        format_selector: req.format.unwrap_or_else(|| config.default_format.clone()),
+        subfolder,
+        title: Some(req.title.unwrap_or_else(|| UNKNOWN_TITLE.into())),
+        username: req.username,
+        password: req.password,
+        attempt: 0,
+        last_error: None,
+        retry_at: None,
+        use_archive: req.use_archive,
+        batch_id: None,
+        playlist_index: None,
+        start_after: req.start_after,
+        limit_rate,
+        title_from_metadata: false,
+        thumbnail: None,
+        filename_template,
+        on_collision: req.on_collision,
+        subtitles: req.subtitles,
+    };
+    Ok((item, req.allow_duplicate))
+}
+
+/// Odtwarza parametry pobierania z zapisanego elementu kolejki.
+pub(crate) fn params_from_queue_item(item: &DownloadQueueItem) -> DownloadParams {
+    let mut output_path = PathBuf::from(downloads_folder());
+    if !item.subfolder.is_empty() {
+        output_path.push(&item.subfolder);
+    }
+
+    DownloadParams {
+        url: item.url.clone(),
+        quality: item.quality.clone(),
+        format_selector: item.format_selector.clone(),
+        output_path,
+        // tytuł zastępczy z /downloadV2 ani odczytany z metadanych nie jest tytułem podanym przez użytkownika
+        custom_title: item.title.clone().filter(|t| t != UNKNOWN_TITLE && !item.title_from_metadata),
+        username: item.username.clone(),
+        password: item.password.clone(),
+        use_archive: item.use_archive,
+        playlist_index: item.playlist_index,
+        limit_rate: item.limit_rate,
+        filename_template: item.filename_template.clone(),
+        on_collision: item.on_collision,
+        subtitles: item.subtitles.clone(),
+    }
+}
+
+/// Plik archiwum pobranych filmów (`--download-archive`), obok pliku kolejki.
+pub(crate) fn download_archive_path() -> PathBuf {
+    data_file_path("download_archive.txt")
+}
+
+
+/// Prefiks linii postępu wypisywanych według `PROGRESS_TEMPLATE`.
+pub(crate) const PROGRESS_PREFIX: &str = "[vda-progress]";
+
+/// Szablon `--progress-template` - pola rozdzielone `|`, brakujące wartości yt-dlp wypisuje jako `NA`.
+pub(crate) const PROGRESS_TEMPLATE: &str = "download:[vda-progress] %(progress.downloaded_bytes)s|%(progress.total_bytes)s|%(progress.total_bytes_estimate)s|%(progress.speed)s|%(progress.eta)s|%(progress.fragment_index)s|%(progress.fragment_count)s";
+
+fn progress_field(value: Option<&str>) -> Option<f64> {
+    value?.trim().parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0)
+}
+
+/// Parsuje linię wypisaną według `PROGRESS_TEMPLATE` (bez prefiksu).
+fn parse_progress_template(data: &str) -> DownloadProgress {
+    let mut fields = data.split('|');
+    let downloaded = progress_field(fields.next());
+    let total = progress_field(fields.next());
+    let total_estimate = progress_field(fields.next());
+    let speed = progress_field(fields.next());
+    let eta = progress_field(fields.next());
+    let fragment_index = progress_field(fields.next());
+    let fragment_count = progress_field(fields.next());
+
+    let total = total.or(total_estimate).filter(|t| *t > 0.0);
+    let percent = match (downloaded, total) {
+        (Some(d), Some(t)) => Some((d / t * 100.0).min(100.0)),
+        _ => None,
+    };
+
+    let mut progress = DownloadProgress::new(JobState::Downloading);
+    progress.percent = percent;
+    progress.downloaded_bytes = downloaded.map(|v| v as u64);
+    progress.total_bytes = total.map(|v| v as u64);
+    progress.speed = speed;
+    progress.eta = eta.map(|v| v as u64);
+    progress.fragment_index = fragment_index.map(|v| v as u64);
+    progress.fragment_count = fragment_count.map(|v| v as u64);
+    progress
+}
+
+/// Rozpoznaje przyczynę błędu na podstawie komunikatów `ERROR:` z yt-dlp.
+pub(crate) fn classify_ytdlp_error(stderr: &str) -> ErrorCode {
+    let text = stderr.to_lowercase();
+    let has = |patterns: &[&str]| patterns.iter().any(|p| text.contains(p));
+
+    if has(&["unsupported url", "is not a valid url"]) {
+        ErrorCode::UnsupportedUrl
+    } else if has(&[
+        "not available in your country",
+        "geo restrict",
+        "geo-restrict",
+        "from your location",
+        "blocked it in your country",
+    ]) {
+        ErrorCode::GeoBlocked
+    } else if has(&[
+        "login required",
+        "sign in to confirm",
+        "only available for registered users",
+        "requires authentication",
+        "invalid username or password",
+        "incorrect password",
+        "use --cookies",
+        "only available for premium users",
+        "requires a premium account",
+    ]) {
+        ErrorCode::LoginRequired
+    } else if has(&["no space left on device", "disk quota exceeded", "not enough space"]) {
+        ErrorCode::InsufficientSpace
+    } else if has(&[
+        "private video",
+        "video is private",
+        "video unavailable",
+        "has been removed",
+        "has been deleted",
+        "no longer available",
+        "does not exist",
+        "http error 404",
+        "http error 410",
+        "account associated with this video has been terminated",
+    ]) {
+        ErrorCode::Unavailable
+    } else if has(&[
+        "unable to download webpage",
+        "timed out",
+        "connection reset",
+        "connection refused",
+        "temporary failure in name resolution",
+        "name or service not known",
+        "network is unreachable",
+        "getaddrinfo failed",
+        "http error 5",
+        // błędy TLS z Pythona: `[SSL: CERTIFICATE_VERIFY_FAILED]`, `SSLError(...)`
+        "[ssl: ",
+        "sslerror",
+    ]) {
+        ErrorCode::Network
+    } else {
+        ErrorCode::DownloadFailed
+    }
+}
+
+/// Loguje linię wyjścia yt-dlp i zwraca etap lub postęp pobierania, jeśli linia go zawiera.
+pub(crate) fn parse_ytdlp_output_line(line: &str, downloaded_file: &mut Option<PathBuf>) -> Option<YtDlpEvent> {
     use std::ffi::OsStr;
 
     let trimmed = line.trim();
     if trimmed.is_empty() {
-        return;
+        return None;
+    }
+
+    if let Some(data) = trimmed.strip_prefix(PROGRESS_PREFIX) {
+        let progress = parse_progress_template(data.trim());
+        match progress.percent {
+            Some(p) => log_info(&format!("⏳ Postęp: {p:.1}%")),
+            None => log_info(&format!("⏳ Postęp: {} B", progress.downloaded_bytes.unwrap_or(0))),
+        }
+        return Some(YtDlpEvent::Progress(progress));
     }
 
     // Główne logowanie postępu
-    if trimmed.contains("[download]") {
+    if trimmed.contains("[download]") && trimmed.contains("has already been recorded in") {
+        log_info(&format!("📚 Film jest już w archiwum pobranych: {trimmed}"));
+        return Some(YtDlpEvent::AlreadyDownloaded);
+    } else if trimmed.contains("[download]") {
         if let Some(idx) = trimmed.find("Destination:") {
             // Znaleziono nazwę pliku
             let fname = trimmed[idx + "Destination:".len()..].trim();
@@ -75,10 +297,16 @@ pub(crate) fn parse_ytdlp_output_line(line: &str, downloaded_file: &mut Option<P
                 .find(|p| p.contains('%'))
                 .unwrap_or(trimmed);
             log_info(&format!("⏳ Postęp: {part}"));
+
+            // starsze yt-dlp bez --progress-template - znamy tylko procent
+            let mut progress = DownloadProgress::new(JobState::Downloading);
+            progress.percent = part.trim_end_matches('%').parse::<f64>().ok();
+            return Some(YtDlpEvent::Progress(progress));
         } else {
             // Inne komunikaty download
             log_info(&format!("⏳ Pobieranie: {trimmed}"));
         }
+        return Some(YtDlpEvent::Phase(JobState::Downloading));
     } else if trimmed.contains("[Merger]") && trimmed.contains("Merging formats into") {
         if let Some(start) = trimmed.find('"') {
             if let Some(end_rel) = trimmed[start + 1..].find('"') {
@@ -88,14 +316,17 @@ pub(crate) fn parse_ytdlp_output_line(line: &str, downloaded_file: &mut Option<P
             }
         }
         log_info(&format!("🔄 Łączenie formatów: {trimmed}"));
+        return Some(YtDlpEvent::Phase(JobState::Merging));
     } else if trimmed.contains("[ExtractAudio]") {
         log_info(&format!("🎵 Konwersja audio: {trimmed}"));
+        return Some(YtDlpEvent::Phase(JobState::Converting));
     } else if trimmed.to_uppercase().contains("ERROR") {
         log_error(&format!("❌ Błąd: {trimmed}"));
     } else {
         // Wszystkie pozostałe linie wypisujemy, żeby nic się nie zgubiło
         log_info(&format!("ℹ️ {trimmed}"));
     }
+    None
 }
 
 
@@ -0,0 +1,303 @@
+use crate::config::ServerConfig;
+
+/// Nazwa szablonu używanego, gdy ani żądanie, ani konfiguracja nie wskazują innego.
+pub(crate) const DEFAULT_TEMPLATE: &str = "default";
+
+/// Wbudowane szablony nazw plików (składnia `-o` yt-dlp, bez rozszerzenia).
+/// Można je nadpisać lub uzupełnić przez `VDA_FILENAME_TEMPLATES`.
+pub(crate) fn builtin_templates() -> Vec<(String, String)> {
+    [
+        ("default", "%(title)s"),
+        ("id", "%(title)s [%(id)s]"),
+        ("uploader", "%(uploader)s/%(title)s"),
+        ("dated", "%(upload_date>%Y-%m-%d)s - %(title)s"),
+        ("playlist", "%(playlist_index)02d - %(title)s"),
+        ("series", "%(series)s/S%(season_number)02dE%(episode_number)02d - %(title)s"),
+    ]
+    .into_iter()
+    .map(|(name, template)| (name.to_string(), template.to_string()))
+    .collect()
+}
+
+/// Sprawdza, czy szablon po rozwinięciu nie wyjdzie poza folder pobierania:
+/// musi być ścieżką względną bez `..`, także wewnątrz pól `%(...)`.
+pub(crate) fn validate(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("Szablon nazwy pliku jest pusty".into());
+    }
+    if template.chars().any(char::is_control) {
+        return Err("Szablon nazwy pliku zawiera znaki sterujące".into());
+    }
+    if template.starts_with(['/', '\\']) || template.chars().nth(1) == Some(':') {
+        return Err("Szablon nazwy pliku musi być ścieżką względną".into());
+    }
+    for part in template.split(['/', '\\']) {
+        if part.trim().is_empty() || part.trim() == ".." {
+            return Err("Szablon nazwy pliku nie może wychodzić poza folder pobierania".into());
+        }
+    }
+
+    // pola mogą wstawiać dowolny tekst (`%(id&..)s`), więc ich treść też jest sprawdzana
+    let mut rest = template;
+    while let Some(start) = rest.find("%(") {
+        let field = &rest[start + 2..];
+        let Some(end) = field.find(')') else {
+            return Err("Niezamknięte pole %(...) w szablonie nazwy pliku".into());
+        };
+        let field = &field[..end];
+        if field.contains("..") || field.contains(['/', '\\']) {
+            return Err(format!("Niedozwolone pole w szablonie nazwy pliku: %({field})"));
+        }
+        rest = &rest[start + 2 + end..];
+    }
+    Ok(())
+}
+
+/// Zwraca sprawdzony szablon: nazwany z konfiguracji albo podany wprost (z polami `%(...)`).
+/// `None` oznacza domyślny szablon serwera.
+pub(crate) fn resolve(config: &ServerConfig, requested: Option<&str>) -> Result<String, String> {
+    let name = requested
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or(&config.filename_template);
+
+    let template = match config.filename_templates.get(&name.to_lowercase()) {
+        Some(template) => template.clone(),
+        None if name.contains("%(") => name.to_string(),
+        None => return Err(format!("Nieznany szablon nazwy pliku: {name}")),
+    };
+    validate(&template)?;
+    Ok(template)
+}
+
+/// Wstawia numer pozycji w pola `%(playlist_index)…` (yt-dlp pobiera pojedyncze filmy
+/// z `--no-playlist`, więc sam by go nie znał). Bez numeru usuwa pole razem z separatorem
+/// (`NA - Tytuł` → `Tytuł`). Zwraca `None`, jeśli szablon go nie używa.
+fn fill_playlist_index(template: &str, index: Option<u32>) -> Option<String> {
+    const FIELD: &str = "%(playlist_index)";
+    const SEPARATORS: [char; 3] = [' ', '-', '_'];
+    if !template.contains(FIELD) {
+        return None;
+    }
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(FIELD) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + FIELD.len()..];
+        let spec_len = spec_len(after);
+        let spec = &after[..spec_len];
+        let conversion = after[spec_len..].chars().next().filter(char::is_ascii_alphabetic);
+
+        rest = &after[spec_len + conversion.map_or(0, char::len_utf8)..];
+
+        let Some(index) = index else {
+            if out.is_empty() || out.ends_with('/') {
+                rest = rest.trim_start_matches(SEPARATORS);
+            } else {
+                out.truncate(out.trim_end_matches(SEPARATORS).len());
+            }
+            continue;
+        };
+        let width = spec.trim_start_matches(['-', '+', ' ', '#']).split('.').next().unwrap_or("");
+        match width.parse::<usize>() {
+            Ok(w) if width.starts_with('0') => out.push_str(&format!("{index:0w$}")),
+            Ok(w) => out.push_str(&format!("{index:w$}")),
+            Err(_) => out.push_str(&index.to_string()),
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Długość specyfikacji formatu pola (`02`, `-5`, `.3`) zaczynającej `after`.
+fn spec_len(after: &str) -> usize {
+    after
+        .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | ' ' | '#' | '.'))
+        .unwrap_or(after.len())
+}
+
+/// Pola o krótkiej, przewidywalnej długości - nie są przycinane, tylko liczone jako
+/// [`SHORT_FIELD_BYTES`], żeby limit przypadł polom tekstowym (tytuł, kanał).
+const SHORT_FIELDS: [&str; 12] = [
+    "id", "upload_date", "release_date", "timestamp", "playlist_index", "autonumber",
+    "season_number", "episode_number", "width", "height", "resolution", "format_id",
+];
+const SHORT_FIELD_BYTES: usize = 20;
+
+/// Ogranicza pola tekstowe składnika ścieżki do `max_bytes` bajtów łącznie
+/// (`%(title)s` → `%(title).80B`), dzieląc limit po równo między nie. yt-dlp
+/// zapisuje plik pod tą nazwą, zanim zajmie się nim [`crate::sanitize`], więc
+/// za długi tytuł kończyłby się błędem "File name too long".
+fn limit_field_bytes(component: &str, max_bytes: usize) -> String {
+    // pozycje znaku konwersji `s` w polach bez podanej precyzji
+    let mut fields = Vec::new();
+    let mut literal = 0;
+    let mut i = 0;
+    while i < component.len() {
+        let rest = &component[i..];
+        if rest.starts_with("%%") {
+            literal += 1;
+            i += 2;
+            continue;
+        }
+        if let Some(close) = rest.strip_prefix("%(").and_then(|r| r.find(')')) {
+            let name = &rest[2..2 + close];
+            let after = &rest[2 + close + 1..];
+            let spec = &after[..spec_len(after)];
+            let conversion_at = i + 2 + close + 1 + spec.len();
+            let conversion = after[spec.len()..].chars().next();
+            let base = &name[..name.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(name.len())];
+            if base == "ext" {
+                // rozszerzenie mieści się w miejscu zarezerwowanym przez `sanitize`
+            } else if SHORT_FIELDS.contains(&base) {
+                literal += SHORT_FIELD_BYTES;
+            } else if conversion == Some('s') && !spec.contains('.') {
+                fields.push(conversion_at);
+            }
+            i = conversion_at + conversion.map_or(0, char::len_utf8);
+            continue;
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        literal += c.len_utf8();
+        i += c.len_utf8();
+    }
+    if fields.is_empty() {
+        return component.to_string();
+    }
+
+    let per_field = (max_bytes.saturating_sub(literal) / fields.len()).max(1);
+    let mut out = String::with_capacity(component.len() + fields.len() * 5);
+    let mut last = 0;
+    for at in fields {
+        out.push_str(&component[last..at]);
+        out.push_str(&format!(".{per_field}B"));
+        last = at + 1;
+    }
+    out.push_str(&component[last..]);
+    out
+}
+
+/// Buduje szablon `-o` względem folderu zadania: podstawia własny tytuł (już oczyszczony)
+/// i numer pozycji playlisty, ogranicza długość pól do `max_bytes` na składnik ścieżki,
+/// a na końcu dokleja rozszerzenie.
+pub(crate) fn output_template(
+    template: &str,
+    custom_title: Option<&str>,
+    playlist_index: Option<u32>,
+    max_bytes: usize,
+) -> String {
+    let mut template = match custom_title {
+        // `%` w tytule yt-dlp wziąłby za początek pola
+        Some(title) => template.replace("%(title)s", &title.replace('%', "%%")),
+        None => template.to_string(),
+    };
+
+    template = match (fill_playlist_index(&template, playlist_index), playlist_index) {
+        (Some(filled), _) => filled,
+        // pozycje playlisty dostają numer na początku nazwy, żeby zachować kolejność
+        (None, Some(index)) => match template.rfind(['/', '\\']) {
+            Some(slash) => format!("{}{index:02} - {}", &template[..=slash], &template[slash + 1..]),
+            None => format!("{index:02} - {template}"),
+        },
+        (None, None) => template,
+    };
+
+    let mut template = template
+        .split('/')
+        .map(|component| limit_field_bytes(component, max_bytes))
+        .collect::<Vec<_>>()
+        .join("/");
+    if !template.ends_with("%(ext)s") {
+        template.push_str(".%(ext)s");
+    }
+    template
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_parent_segments() {
+        assert!(validate("../%(title)s").is_err());
+        assert!(validate("%(uploader)s/../../%(title)s").is_err());
+        assert!(validate("%(uploader)s\\..\\%(title)s").is_err());
+        assert!(validate("%(uploader)s//%(title)s").is_err());
+        // `..` w środku nazwy nie jest segmentem
+        assert!(validate("%(title)s...%(id)s").is_ok());
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(validate("/etc/%(title)s").is_err());
+        assert!(validate("\\\\server\\share\\%(title)s").is_err());
+        assert!(validate("C:%(title)s").is_err());
+        assert!(validate("C:\\Users\\%(title)s").is_err());
+        assert!(validate("d:/%(title)s").is_err());
+    }
+
+    #[test]
+    fn rejects_paths_inside_fields() {
+        assert!(validate("%(title&/etc/passwd)s").is_err());
+        assert!(validate("%(title|..)s").is_err());
+        assert!(validate("%(title&a\\b)s").is_err());
+        assert!(validate("%(title").is_err());
+        assert!(validate("%(upload_date>%Y-%m-%d)s - %(title)s").is_ok());
+    }
+
+    #[test]
+    fn resolves_names_and_raw_templates() {
+        let mut config = ServerConfig::from_env();
+        config.filename_templates = builtin_templates().into_iter().collect();
+        config.filename_template = DEFAULT_TEMPLATE.into();
+
+        assert_eq!(resolve(&config, None).unwrap(), "%(title)s");
+        assert_eq!(resolve(&config, Some(" ID ")).unwrap(), "%(title)s [%(id)s]");
+        assert_eq!(resolve(&config, Some("%(id)s")).unwrap(), "%(id)s");
+        assert!(resolve(&config, Some("nieznany")).is_err());
+        assert!(resolve(&config, Some("../%(title)s")).is_err());
+    }
+
+    #[test]
+    fn escapes_percent_in_custom_title() {
+        assert_eq!(output_template("%(title)s", Some("100% prawdy"), None, 95), "100%% prawdy.%(ext)s");
+        assert_eq!(
+            output_template("%(title)s [%(id)s]", Some("50%(x)s"), None, 95),
+            "50%%(x)s [%(id)s].%(ext)s"
+        );
+    }
+
+    #[test]
+    fn fills_playlist_index() {
+        assert_eq!(output_template("%(playlist_index)02d - %(title)s", Some("T"), Some(3), 95), "03 - T.%(ext)s");
+        assert_eq!(output_template("%(playlist_index)s. %(title)s", Some("T"), Some(12), 95), "12. T.%(ext)s");
+        // szablon bez pola - numer trafia na początek nazwy pliku
+        assert_eq!(output_template("%(uploader)s/%(title)s", Some("T"), Some(7), 95), "%(uploader).95B/07 - T.%(ext)s");
+    }
+
+    #[test]
+    fn drops_missing_playlist_index() {
+        // wbudowany szablon `playlist` dla filmu spoza playlisty dawał `NA - Tytuł`
+        assert_eq!(output_template("%(playlist_index)02d - %(title)s", Some("T"), None, 95), "T.%(ext)s");
+        assert_eq!(output_template("%(title)s - %(playlist_index)s", Some("T"), None, 95), "T.%(ext)s");
+        assert_eq!(output_template("%(uploader)s/%(playlist_index)s_%(title)s", Some("T"), None, 95), "%(uploader).95B/T.%(ext)s");
+        assert_eq!(output_template("%(title)s", Some("T"), None, 95), "T.%(ext)s");
+    }
+
+    #[test]
+    fn limits_text_fields_to_name_bytes() {
+        // eCryptfs: 143 - 48 bajtów zapasu
+        assert_eq!(output_template("%(title)s", None, None, 95), "%(title).95B.%(ext)s");
+        // krótkie pola i tekst stały zmniejszają limit tytułu
+        assert_eq!(output_template("%(title)s [%(id)s]", None, None, 95), "%(title).72B [%(id)s].%(ext)s");
+        // każdy folder ma własny limit
+        assert_eq!(
+            output_template("%(uploader)s/%(title)s", None, None, 95),
+            "%(uploader).95B/%(title).95B.%(ext)s"
+        );
+        // podana precyzja i własny tytuł zostają bez zmian
+        assert_eq!(output_template("%(title).20s", None, None, 95), "%(title).20s.%(ext)s");
+        assert_eq!(output_template("%(title)s", Some("Mój film"), None, 95), "Mój film.%(ext)s");
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs;
+use std::io::ErrorKind;
+use crate::dodatkowe_funkcje::data_file_path;
+use crate::models::{HistoryEntry, HistoryPage, HistoryPruneQuery, HistoryQuery, JobStatus};
+use crate::{log_error, log_info, AppState};
+
+static HISTORY_FILE: &str = "download_history.json";
+
+/// Domyślna i maksymalna liczba wpisów na stronie `GET /history`.
+const DEFAULT_PAGE: usize = 50;
+const MAX_PAGE: usize = 500;
+
+pub(crate) fn load_history() -> Vec<HistoryEntry> {
+    let path = data_file_path(HISTORY_FILE);
+
+    match fs::read_to_string(&path) {
+        Ok(data) => match serde_json::from_str::<Vec<HistoryEntry>>(&data) {
+            Ok(history) => history,
+            Err(e) => {
+                log_error(&format!("Nie udało się odczytać historii JSON z {:?}: {e}", path));
+                vec![]
+            }
+        },
+        // brak pliku przy pierwszym uruchomieniu
+        Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+        Err(e) => {
+            log_error(&format!("Nie udało się wczytać pliku {:?}: {e}", path));
+            vec![]
+        }
+    }
+}
+
+fn save_history(history: &[HistoryEntry]) {
+    let json = match serde_json::to_string_pretty(history) {
+        Ok(j) => j,
+        Err(e) => {
+            log_error(&format!("Błąd serializacji historii do JSON: {e}"));
+            return;
+        }
+    };
+
+    if let Err(e) = fs::write(data_file_path(HISTORY_FILE), json) {
+        log_error(&format!("Nie udało się zapisać historii: {e}"));
+    }
+}
+
+/// Dopisuje zakończone zadanie do historii i zapisuje ją na dysk.
+pub(crate) fn record(app_state: &AppState, job: &JobStatus) {
+    let finished_at = job.finished_at.unwrap_or(job.created_at);
+    let size_bytes = job
+        .output_file
+        .as_ref()
+        .and_then(|f| fs::metadata(f).ok())
+        .map(|m| m.len());
+
+    let entry = HistoryEntry {
+        id: job.id,
+        url: job.url.clone(),
+        title: job.title.clone(),
+        outcome: job.state,
+        output_file: job.output_file.clone(),
+        size_bytes,
+        duration: job.started_at.map(|start| (finished_at - start).max(0.0)),
+        created_at: job.created_at,
+        started_at: job.started_at,
+        finished_at,
+        attempt: job.attempt,
+        error: job.error.clone(),
+        error_code: job.error_code,
+    };
+
+    let mut history = app_state.history.lock().unwrap();
+    history.retain(|e| e.id != job.id);
+    history.push(entry);
+    save_history(&history);
+}
+
+/// Stan zakończonego zadania odtworzony z wpisu historii (bez postępu i numeru partii).
+pub(crate) fn job_status(app_state: &AppState, id: u64) -> Option<JobStatus> {
+    let history = app_state.history.lock().unwrap();
+    let entry = history.iter().rev().find(|e| e.id == id)?;
+    Some(JobStatus {
+        id: entry.id,
+        state: entry.outcome,
+        batch_id: None,
+        attempt: entry.attempt,
+        url: entry.url.clone(),
+        title: entry.title.clone(),
+        thumbnail: None,
+        created_at: entry.created_at,
+        started_at: entry.started_at,
+        finished_at: Some(entry.finished_at),
+        output_file: entry.output_file.clone(),
+        collision: None,
+        error: entry.error.clone(),
+        error_code: entry.error_code,
+        progress: None,
+    })
+}
+
+/// Zwraca stronę historii pasującą do filtrów, od najnowszych wpisów.
+pub(crate) fn query(app_state: &AppState, query: &HistoryQuery) -> HistoryPage {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let needle = query.q.as_ref().map(|q| q.trim().to_lowercase()).filter(|q| !q.is_empty());
+
+    let history = app_state.history.lock().unwrap();
+    let matching: Vec<&HistoryEntry> = history
+        .iter()
+        .rev()
+        .filter(|e| query.outcome.is_none_or(|o| e.outcome == o))
+        .filter(|e| query.since.is_none_or(|t| e.finished_at >= t))
+        .filter(|e| query.until.is_none_or(|t| e.finished_at < t))
+        .filter(|e| {
+            needle.as_ref().is_none_or(|n| {
+                [Some(&e.url), e.title.as_ref(), e.output_file.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|field| field.to_lowercase().contains(n))
+            })
+        })
+        .collect();
+
+    HistoryPage {
+        total: matching.len(),
+        offset: query.offset,
+        limit,
+        items: matching.into_iter().skip(query.offset).take(limit).cloned().collect(),
+    }
+}
+
+/// Usuwa pojedynczy wpis. Zwraca `false`, jeśli go nie było.
+pub(crate) fn remove(app_state: &AppState, id: u64) -> bool {
+    let mut history = app_state.history.lock().unwrap();
+    let before = history.len();
+    history.retain(|e| e.id != id);
+    if history.len() == before {
+        return false;
+    }
+    save_history(&history);
+    true
+}
+
+/// Usuwa wpisy pasujące do kryteriów i zwraca ich liczbę.
+/// `None` oznacza brak kryteriów (bez `all=true` nic nie jest usuwane).
+pub(crate) fn prune(app_state: &AppState, query: &HistoryPruneQuery) -> Option<usize> {
+    if query.before.is_none() && query.outcome.is_none() && !query.all {
+        return None;
+    }
+
+    let mut history = app_state.history.lock().unwrap();
+    let before = history.len();
+    history.retain(|e| {
+        let matches = query.before.is_none_or(|t| e.finished_at < t)
+            && query.outcome.is_none_or(|o| e.outcome == o);
+        !matches
+    });
+
+    let removed = before - history.len();
+    if removed > 0 {
+        save_history(&history);
+        log_info(&format!("🧹 Usunięto {removed} wpisów z historii"));
+    }
+    Some(removed)
+}
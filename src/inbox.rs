@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use actix_web::web;
+use crate::dodatkowe_funkcje::{current_unix_time_f64, downloads_folder, queue_item_from_request};
+use crate::models::DownloadRequest;
+use crate::pobieracz::{enqueue_downloads, next_job_id, Enqueued};
+use crate::{log_error, log_info, probe, AppState};
+
+/// Co ile sekund skanowany jest folder inbox.
+const POLL_SECS: u64 = 5;
+
+/// Ile sekund plik musi pozostać niezmieniony, zanim zostanie odczytany
+/// (kopiowanie przez SMB/File Station może jeszcze trwać).
+const SETTLE_SECS: u64 = 3;
+
+const PROCESSED_DIR: &str = "processed";
+const FAILED_DIR: &str = "failed";
+
+/// Odczytuje adresy z pliku według rozszerzenia: `.txt` (adres w linii, `#` to komentarz),
+/// `.url` (skrót Windows) lub `.webloc` (skrót macOS). `None` dla innych plików.
+fn read_urls(path: &Path) -> Option<Result<Vec<String>, String>> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    if !matches!(ext.as_str(), "txt" | "url" | "webloc") {
+        return None;
+    }
+
+    let bytes = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) => return Some(Err(format!("Nie udało się odczytać pliku: {e}"))),
+    };
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{feff}');
+
+    let urls = match ext.as_str() {
+        "txt" => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect(),
+        "url" => text
+            .lines()
+            .map(str::trim)
+            .find(|line| line.get(..4).is_some_and(|key| key.eq_ignore_ascii_case("url=")))
+            .map(|line| vec![line[4..].trim().to_string()])
+            .unwrap_or_default(),
+        _ if text.starts_with("bplist") => {
+            return Some(Err("Binarny plik .webloc nie jest obsługiwany".into()));
+        }
+        _ => webloc_url(text).into_iter().collect(),
+    };
+
+    if urls.is_empty() {
+        return Some(Err("Plik nie zawiera żadnego adresu".into()));
+    }
+    Some(Ok(urls))
+}
+
+/// Wyciąga adres z XML-owego pliku `.webloc` (`<key>URL</key><string>…</string>`).
+fn webloc_url(text: &str) -> Option<String> {
+    let after_key = &text[text.find("<key>URL</key>")? + "<key>URL</key>".len()..];
+    let start = after_key.find("<string>")? + "<string>".len();
+    let end = start + after_key[start..].find("</string>")?;
+    let url = after_key[start..end]
+        .trim()
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'");
+    Some(url)
+}
+
+/// Dodaje adresy z pliku do kolejki jako jedną partię. Zwraca liczbę dodanych
+/// (duplikaty nie są błędem) albo opis błędu, jeśli żaden adres nie był poprawny.
+fn enqueue_file(app_state: &web::Data<AppState>, name: &str, urls: Vec<String>) -> Result<usize, String> {
+    let mut items = Vec::with_capacity(urls.len());
+    let mut invalid = Vec::new();
+    for url in urls {
+        let req = DownloadRequest { url: url.clone(), ..Default::default() };
+        match queue_item_from_request(req, &app_state.config) {
+            Ok(item) => items.push(item),
+            Err(e) => invalid.push(format!("{url}: {e}")),
+        }
+    }
+
+    if items.is_empty() {
+        return Err(invalid.join("\n"));
+    }
+    for line in &invalid {
+        log_error(&format!("📥 Inbox {name}: pominięto {line}"));
+    }
+
+    if let Err(e) = fs::create_dir_all(downloads_folder()) {
+        return Err(format!("Nie udało się utworzyć folderu pobierania: {e}"));
+    }
+
+    let batch_id = next_job_id(app_state);
+    for (item, _) in items.iter_mut() {
+        item.batch_id = Some(batch_id);
+    }
+    let total = items.len();
+    let added: Vec<u64> = enqueue_downloads(app_state, items)
+        .into_iter()
+        .filter_map(|e| match e {
+            Enqueued::Added { id, .. } => Some(id),
+            Enqueued::Duplicate(_) => None,
+        })
+        .collect();
+    for &id in &added {
+        probe::resolve_title_later(app_state, id);
+    }
+
+    log_info(&format!("📥 Inbox {name}: partia #{batch_id}, dodano {} z {total} adresów", added.len()));
+    Ok(added.len())
+}
+
+/// Przenosi plik do podfolderu `processed/` lub `failed/`, nie nadpisując
+/// wcześniej przeniesionych plików o tej samej nazwie.
+fn move_to(path: &Path, dir: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Nie udało się utworzyć folderu {:?}: {e}", dir))?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut target = dir.join(&name);
+    if target.exists() {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let stamp = current_unix_time_f64() as u64;
+        target = match path.extension() {
+            Some(ext) => dir.join(format!("{stem}-{stamp}.{}", ext.to_string_lossy())),
+            None => dir.join(format!("{stem}-{stamp}")),
+        };
+    }
+
+    fs::rename(path, &target).map_err(|e| format!("Nie udało się przenieść {:?}: {e}", path))?;
+    Ok(target)
+}
+
+/// Czy plik jest kompletny - nie był modyfikowany przez ostatnie `SETTLE_SECS` sekund.
+fn is_settled(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= Duration::from_secs(SETTLE_SECS))
+}
+
+fn process_file(app_state: &web::Data<AppState>, inbox: &Path, path: &Path) {
+    if !is_settled(path) {
+        return;
+    }
+    let Some(read) = read_urls(path) else {
+        return;
+    };
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    match read.and_then(|urls| enqueue_file(app_state, &name, urls)) {
+        Ok(_) => {
+            if let Err(e) = move_to(path, &inbox.join(PROCESSED_DIR)) {
+                log_error(&format!("📥 Inbox: {e}"));
+            }
+        }
+        Err(reason) => {
+            log_error(&format!("❌ Inbox {name}: {reason}"));
+            match move_to(path, &inbox.join(FAILED_DIR)) {
+                // opis błędu obok pliku - widoczny w File Station bez zaglądania do logów
+                Ok(target) => {
+                    let mut report = target.into_os_string();
+                    report.push(".error.txt");
+                    if let Err(e) = fs::write(&report, format!("{reason}\n")) {
+                        log_error(&format!("📥 Inbox: nie udało się zapisać {:?}: {e}", report));
+                    }
+                }
+                Err(e) => log_error(&format!("📥 Inbox: {e}")),
+            }
+        }
+    }
+}
+
+fn scan(app_state: &web::Data<AppState>, inbox: &Path) {
+    let entries = match fs::read_dir(inbox) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log_error(&format!("📥 Nie udało się odczytać folderu inbox {:?}: {e}", inbox));
+            return;
+        }
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect();
+    files.sort();
+
+    for path in files {
+        process_file(app_state, inbox, &path);
+    }
+}
+
+/// Pętla w tle - co kilka sekund pobiera adresy z plików wrzuconych do folderu inbox.
+pub(crate) async fn poll_loop(app_state: web::Data<AppState>, inbox: PathBuf) {
+    if let Err(e) = fs::create_dir_all(&inbox) {
+        log_error(&format!("📥 Nie udało się utworzyć folderu inbox {:?}: {e}", inbox));
+    }
+    log_info(&format!("📥 Obserwuję folder inbox: {:?}", inbox));
+
+    loop {
+        // odczyt i przenoszenie plików blokują, więc nie mogą zajmować wątku serwera
+        let (state, dir) = (app_state.clone(), inbox.clone());
+        if let Err(e) = tokio::task::spawn_blocking(move || scan(&state, &dir)).await {
+            log_error(&format!("📥 Błąd skanowania folderu inbox: {e}"));
+        }
+        tokio::time::sleep(Duration::from_secs(POLL_SECS)).await;
+    }
+}
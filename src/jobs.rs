@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use crate::dodatkowe_funkcje::{current_unix_time_f64, save_queue_to_file};
+use crate::models::{DownloadProgress, DownloadQueueItem, JobEvent, JobResult, JobState, JobStatus, QueueEntry, WaitReason, UNKNOWN_TITLE};
+use crate::{disk, history, log_info, AppState};
+
+/// Wysyła zdarzenie do subskrybentów `/events`. Brak słuchaczy nie jest błędem.
+pub(crate) fn emit(app_state: &AppState, event: JobEvent) {
+    let _ = app_state.events.send(event);
+}
+
+/// Jak długo (w sekundach) zakończone zadanie zostaje w pamięci - później jego stan
+/// odczytywany jest z historii.
+const FINISHED_RETENTION: f64 = 1800.0;
+
+/// Usuwa zadania zakończone dawniej niż `FINISHED_RETENTION` temu (są już zapisane w historii).
+fn prune_finished(jobs: &mut HashMap<u64, JobStatus>, now: f64) {
+    jobs.retain(|_, job| !job.state.is_finished() || job.finished_at.is_none_or(|t| now - t < FINISHED_RETENTION));
+}
+
+/// Rejestruje nowe zadanie w stanie `Queued`.
+pub(crate) fn register_job(app_state: &AppState, item: &DownloadQueueItem) {
+    let status = JobStatus {
+        id: item.id,
+        state: JobState::Queued,
+        batch_id: item.batch_id,
+        attempt: item.attempt,
+        url: item.url.clone(),
+        title: item.title.clone(),
+        thumbnail: item.thumbnail.clone(),
+        created_at: current_unix_time_f64(),
+        started_at: None,
+        finished_at: None,
+        output_file: None,
+        collision: None,
+        error: item.last_error.clone(),
+        error_code: None,
+        progress: None,
+    };
+    {
+        let mut jobs = app_state.jobs.lock().unwrap();
+        // subskrypcje, inbox i partie dodają zadania bez końca - stare nie mogą zostawać w pamięci
+        prune_finished(&mut jobs, status.created_at);
+        jobs.insert(item.id, status);
+    }
+
+    emit(app_state, JobEvent::JobAdded {
+        id: item.id,
+        url: item.url.clone(),
+        title: item.title.clone(),
+    });
+}
+
+/// Stan zadania. Zadania usunięte już z pamięci odtwarzane są z historii.
+pub(crate) fn job_status(app_state: &AppState, id: u64) -> Option<JobStatus> {
+    let job = app_state.jobs.lock().unwrap().get(&id).cloned();
+    job.or_else(|| history::job_status(app_state, id))
+}
+
+pub(crate) fn is_cancelled(app_state: &AppState, id: u64) -> bool {
+    app_state
+        .jobs
+        .lock()
+        .unwrap()
+        .get(&id)
+        .is_some_and(|job| job.state == JobState::Cancelled)
+}
+
+/// Zmienia stan zadania. Zakończonych zadań nie da się już przestawić.
+/// Zwraca `true`, jeśli stan faktycznie się zmienił.
+pub(crate) fn set_job_state(app_state: &AppState, id: u64, state: JobState) -> bool {
+    transition(app_state, id, state, |_| {})
+}
+
+/// Zmienia stan zadania i w tym samym kroku uzupełnia jego pola (`update`).
+/// Zadanie, które osiągnęło stan końcowy, trafia do historii.
+fn transition(app_state: &AppState, id: u64, state: JobState, update: impl FnOnce(&mut JobStatus)) -> bool {
+    let finished = {
+        let mut jobs = app_state.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&id) else {
+            return false;
+        };
+
+        if job.state == state || job.state.is_finished() {
+            return false;
+        }
+
+        let now = current_unix_time_f64();
+        if job.started_at.is_none() && state != JobState::Queued && !state.is_finished() {
+            job.started_at = Some(now);
+        }
+        if state.is_finished() {
+            job.finished_at = Some(now);
+        }
+        job.state = state;
+        if state != JobState::Queued {
+            job.progress
+                .get_or_insert_with(|| DownloadProgress::new(state))
+                .phase = state;
+        }
+        update(job);
+
+        state.is_finished().then(|| job.clone())
+    };
+
+    emit(app_state, JobEvent::PhaseChange { id, state });
+    log_info(&format!("🔁 Zadanie #{id}: {state:?}"));
+
+    if let Some(job) = finished {
+        history::record(app_state, &job);
+    }
+    true
+}
+
+/// Przywraca zadanie do oczekujących po nieudanej próbie, zostawiając informację o błędzie.
+pub(crate) fn requeue_job(app_state: &AppState, id: u64, attempt: u32, result: &JobResult) {
+    set_job_state(app_state, id, JobState::Queued);
+    disk::release(app_state, id);
+
+    if let Some(job) = app_state.jobs.lock().unwrap().get_mut(&id) {
+        job.attempt = attempt;
+        job.error = result.error.clone();
+        job.error_code = result.error_code;
+        job.progress = None;
+    }
+}
+
+/// Aktualizuje postęp pobierania; pierwsza linia postępu przełącza zadanie w `Downloading`.
+pub(crate) fn update_progress(app_state: &AppState, id: u64, mut progress: DownloadProgress) {
+    set_job_state(app_state, id, JobState::Downloading);
+
+    {
+        let mut jobs = app_state.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+        if job.state.is_finished() {
+            return;
+        }
+        progress.phase = job.state;
+        job.progress = Some(progress.clone());
+    }
+
+    emit(app_state, JobEvent::Progress { id, progress });
+}
+
+/// Uzupełnia tytuł i miniaturę zadania dodanego bez tytułu. Tytuł podany przez
+/// użytkownika nie jest nadpisywany. Zwraca `false`, jeśli nic się nie zmieniło.
+pub(crate) fn set_metadata(app_state: &AppState, id: u64, title: String, thumbnail: Option<String>) -> bool {
+    {
+        let mut queue = app_state.queue.lock().unwrap();
+        let Some(item) = queue.iter_mut().find(|item| item.id == id) else {
+            return false;
+        };
+        if item.title.as_deref().is_some_and(|t| t != UNKNOWN_TITLE) && !item.title_from_metadata {
+            return false;
+        }
+        item.title = Some(title.clone());
+        item.title_from_metadata = true;
+        item.thumbnail = thumbnail.clone();
+        save_queue_to_file(&queue);
+    }
+
+    if let Some(job) = app_state.jobs.lock().unwrap().get_mut(&id) {
+        job.title = Some(title.clone());
+        job.thumbnail = thumbnail.clone();
+    }
+
+    log_info(&format!("🏷️ Zadanie #{id}: {title}"));
+    emit(app_state, JobEvent::MetadataResolved { id, title, thumbnail });
+    true
+}
+
+/// Pozycja zadania wśród oczekujących (od 1). `None`, jeśli zadanie już ruszyło.
+pub(crate) fn queue_position(app_state: &AppState, id: u64) -> Option<usize> {
+    let queue = app_state.queue.lock().unwrap();
+    let jobs = app_state.jobs.lock().unwrap();
+
+    queue
+        .iter()
+        .filter(|item| jobs.get(&item.id).is_some_and(|j| j.state == JobState::Queued))
+        .position(|item| item.id == id)
+        .map(|p| p + 1)
+}
+
+/// Łączy elementy kolejki z ich stanem i postępem.
+pub(crate) fn queue_entries(app_state: &AppState) -> Vec<QueueEntry> {
+    let queue = app_state.queue.lock().unwrap().clone();
+    let jobs = app_state.jobs.lock().unwrap();
+
+    let now = current_unix_time_f64();
+    let mut waiting = 0;
+    queue
+        .into_iter()
+        .map(|item| {
+            let job = jobs.get(&item.id);
+            let state = job.map(|j| j.state);
+            let position = if state == Some(JobState::Queued) {
+                waiting += 1;
+                Some(waiting)
+            } else {
+                None
+            };
+            let (reason, until) = if state == Some(JobState::Queued) {
+                wait_reason(app_state, &item, now)
+            } else {
+                (None, None)
+            };
+            QueueEntry {
+                state,
+                position,
+                waiting: reason,
+                waiting_until: until,
+                progress: job.and_then(|j| j.progress.clone()),
+                item,
+            }
+        })
+        .collect()
+}
+
+/// Dlaczego oczekujące zadanie nie może jeszcze wystartować i do kiedy.
+fn wait_reason(app_state: &AppState, item: &DownloadQueueItem, now: f64) -> (Option<WaitReason>, Option<f64>) {
+    if let Some(t) = item.start_after.filter(|t| *t > now) {
+        return (Some(WaitReason::StartAfter), Some(t));
+    }
+    if let Some(t) = item.retry_at.filter(|t| *t > now) {
+        return (Some(WaitReason::Retry), Some(t));
+    }
+    if !app_state.config.in_download_window(now) {
+        return (Some(WaitReason::Window), app_state.config.next_window_start(now));
+    }
+    if app_state.low_space.load(Ordering::SeqCst) {
+        return (Some(WaitReason::LowSpace), None);
+    }
+    (None, None)
+}
+
+/// Zapisuje wynik pobierania i ustawia stan końcowy zadania.
+pub(crate) fn finish_job(app_state: &AppState, id: u64, result: &JobResult) {
+    let state = result.final_state();
+    disk::release(app_state, id);
+
+    let changed = transition(app_state, id, state, |job| {
+        job.output_file = result.output_file.clone();
+        job.collision = result.collision;
+        job.error = result.error.clone();
+        job.error_code = result.error_code;
+    });
+    if !changed {
+        return;
+    }
+
+    let event = match state {
+        JobState::Completed => JobEvent::Completed {
+            id,
+            output_file: result.output_file.clone(),
+        },
+        JobState::AlreadyDownloaded => JobEvent::AlreadyDownloaded { id },
+        JobState::Skipped => JobEvent::Skipped {
+            id,
+            output_file: result.output_file.clone(),
+        },
+        _ => JobEvent::Failed {
+            id,
+            error: result.error.clone(),
+            error_code: result.error_code,
+        },
+    };
+    emit(app_state, event);
+}
@@ -1,8 +1,19 @@
 mod models;
+mod config;
 mod setup;
 mod pobieracz;
 mod dodatkowe_funkcje;
 mod api_handler;
+mod jobs;
+mod sites;
+mod history;
+mod subscriptions;
+mod inbox;
+mod probe;
+mod disk;
+mod filename_template;
+mod sanitize;
+mod subtitles;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
@@ -13,9 +24,11 @@ use std::fs;
 use std::io::{empty, BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use time::OffsetDateTime;
-use tokio::sync::{mpsc, oneshot};
+use std::collections::VecDeque;
+use tokio::sync::{broadcast, oneshot, Notify};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use serde_json;
 #[allow(unused_imports)]
@@ -24,11 +37,13 @@ use std::net::{IpAddr, Ipv4Addr};
 #[cfg(target_family = "unix")]
 use std::os::unix::fs::PermissionsExt;
 use dirs::download_dir;
-use crate::api_handler::{check_ytdlp_handler, download_handler, download_handlerv2, queue_handler, status_handler, verify_premium_handler};
+use crate::api_handler::{batch_download_handler, cancel_batch_handler, cancel_handler, check_ytdlp_handler, download_handler, download_handlerv2, events_handler, filename_templates_handler, history_delete_handler, history_handler, history_prune_handler, job_handler, probe_handler, probe_post_handler, queue_handler, rate_limit_handler, set_rate_limit_handler, status_handler, subscription_check_handler, subscription_create_handler, subscription_delete_handler, subscription_handler, subscription_update_handler, subscriptions_handler, subtitles_handler, subtitles_post_handler, verify_premium_handler};
 use crate::dodatkowe_funkcje::{downloads_folder, load_queue_from_file, log_info, log_error, save_queue_to_file, set_global_download_dir};
-use crate::models::{DownloadParams, DownloadQueueItem, DownloadRequest, DownloadResponse, JobResult, StatusResponse, YtDlpStatus};
-use crate::pobieracz::download_worker_loop;
+use crate::models::{DownloadParams, DownloadQueueItem, DownloadRequest, DownloadResponse, HistoryEntry, JobEvent, JobResult, JobStatus, Subscription, StatusResponse, YtDlpStatus};
+use crate::pobieracz::{download_worker_loop, restore_queue};
 use crate::setup::{is_synology, port_is_available};
+use crate::config::ServerConfig;
+use crate::sites::SiteLimiter;
 
 
 
@@ -63,6 +78,8 @@ struct VerifyPremiumResponse {
 struct DownloadJob {
     id: u64,
     params: DownloadParams,
+    /// czas uniksowy, przed którym zadanie nie może wystartować
+    not_before: Option<f64>,
     resp_tx: oneshot::Sender<JobResult>,
 }
 
@@ -71,9 +88,26 @@ struct DownloadJob {
 
 
 struct AppState {
-    job_sender: mpsc::Sender<DownloadJob>,
+    /// zadania czekające na wolnego workera, w kolejności dodania
+    pending: Mutex<VecDeque<DownloadJob>>,
+    /// budzi workery, gdy pojawi się zadanie albo zwolni się miejsce
+    dispatch: Notify,
+    sites: SiteLimiter,
     job_counter: AtomicU64,
+    /// globalny limit prędkości w B/s (0 - bez limitu), zmieniany przez `/admin/rate-limit`
+    rate_limit: AtomicU64,
+    /// kolejka wstrzymana, bo na dysku zostało mniej niż `low_space` wolnego miejsca
+    low_space: AtomicBool,
+    /// miejsce na dysku zarezerwowane przez trwające pobrania (ID zadania → bajty)
+    reserved_space: Mutex<HashMap<u64, u64>>,
     queue: Mutex<Vec<DownloadQueueItem>>,
+    jobs: Mutex<HashMap<u64, JobStatus>>,
+    /// zakończone zadania, zapisywane w `download_history.json`
+    history: Mutex<Vec<HistoryEntry>>,
+    /// obserwowane playlisty i kanały, zapisywane w `subscriptions.json`
+    subscriptions: Mutex<Vec<Subscription>>,
+    events: broadcast::Sender<JobEvent>,
+    config: ServerConfig,
 }
 
 
@@ -95,6 +129,7 @@ async fn main() -> std::io::Result<()> {
     let server_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
     let mut verbose = false;
     let mut download_dir = String::new();
+    let mut config = ServerConfig::from_env();
 
 
     let args: Vec<String> = env::args().collect();
@@ -118,7 +153,14 @@ async fn main() -> std::io::Result<()> {
             "--verbose" | "-v" => {
                 verbose = true;
             }
-            _ => {}
+            flag => {
+                // pozostałe opcje serwera (--workers itd.)
+                if let Some(value) = args.get(i + 1) {
+                    if config.apply_cli_arg(flag, value) {
+                        i += 1;
+                    }
+                }
+            }
         }
         i += 1;
     }
@@ -127,6 +169,19 @@ async fn main() -> std::io::Result<()> {
         log_info("Włączono tryb verbose");
     }
 
+    // błędny szablon z konfiguracji nie może zablokować wszystkich pobrań
+    config.filename_templates.retain(|name, template| match filename_template::validate(template) {
+        Ok(()) => true,
+        Err(e) => {
+            log_error(&format!("❌ Pomijam szablon nazwy pliku {name}: {e}"));
+            false
+        }
+    });
+    if let Err(e) = filename_template::resolve(&config, None) {
+        log_error(&format!("❌ {e} - używam szablonu {}", filename_template::DEFAULT_TEMPLATE));
+        config.filename_template = filename_template::DEFAULT_TEMPLATE.into();
+    }
+
     setup::check_dependencies();
 
     if !download_dir.is_empty(){
@@ -143,23 +198,66 @@ async fn main() -> std::io::Result<()> {
         version
     ));
     log_info(&format!("📁 Folder pobierania: {downloads}"));
+    if config.download_archive {
+        log_info(&format!(
+            "📚 Archiwum pobranych: {}",
+            dodatkowe_funkcje::download_archive_path().to_string_lossy()
+        ));
+    }
 
     let _ = setup::check_ytdlp_once();
 
-    let (tx, rx) = mpsc::channel::<DownloadJob>(100);
+    let (events_tx, _) = broadcast::channel::<JobEvent>(256);
 
 
 
     let initial_queue = load_queue_from_file();
     log_info(&format!("📂 Wczytano {} zadań z poprzedniej sesji", initial_queue.len()));
 
+    let history = history::load_history();
+    log_info(&format!("📜 Wczytano {} wpisów historii", history.len()));
+
+    // nowe ID (zadań i partii) muszą zaczynać się za najwyższym ID z zapisanej kolejki i historii
+    let last_id = initial_queue
+        .iter()
+        .flat_map(|item| [Some(item.id), item.batch_id])
+        .flatten()
+        .chain(history.iter().map(|entry| entry.id))
+        .max()
+        .unwrap_or(0);
+
     let app_state = web::Data::new(AppState {
-        job_sender: tx,
-        job_counter: AtomicU64::new(0),
+        pending: Mutex::new(VecDeque::new()),
+        dispatch: Notify::new(),
+        sites: SiteLimiter::new(&config),
+        job_counter: AtomicU64::new(last_id),
+        rate_limit: AtomicU64::new(config.rate_limit.unwrap_or(0)),
+        low_space: AtomicBool::new(false),
+        reserved_space: Mutex::new(HashMap::new()),
         queue: Mutex::new(initial_queue),
+        jobs: Mutex::new(HashMap::new()),
+        history: Mutex::new(history),
+        subscriptions: Mutex::new(subscriptions::load_subscriptions()),
+        events: events_tx,
+        config,
     });
 
-    tokio::spawn(download_worker_loop(rx, app_state.clone()));
+    for item in app_state.queue.lock().unwrap().iter() {
+        jobs::register_job(&app_state, item);
+    }
+
+    restore_queue(&app_state);
+    // zadania bez tytułu z poprzedniego uruchomienia
+    let restored: Vec<u64> = app_state.queue.lock().unwrap().iter().map(|item| item.id).collect();
+    for id in restored {
+        probe::resolve_title_later(&app_state, id);
+    }
+    tokio::spawn(disk::monitor_loop(app_state.clone()));
+    tokio::spawn(download_worker_loop(app_state.clone()));
+    tokio::spawn(subscriptions::poll_loop(app_state.clone()));
+    if let Some(inbox) = app_state.config.inbox_dir.clone() {
+        tokio::spawn(inbox::poll_loop(app_state.clone(), inbox));
+    }
 
 
     let max_prub = 10;
@@ -190,7 +288,7 @@ async fn main() -> std::io::Result<()> {
             .wrap(
                 Cors::default()
                     .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
                     .allow_any_header()
                     .max_age(3600),
             )
@@ -198,8 +296,29 @@ async fn main() -> std::io::Result<()> {
             .route("/status", web::get().to(status_handler))
             .route("/check-ytdlp", web::get().to(check_ytdlp_handler))
             .route("/queue", web::get().to(queue_handler))
+            .route("/queue/{id}", web::delete().to(cancel_handler))
+            .route("/batches/{id}", web::delete().to(cancel_batch_handler))
+            .route("/jobs/{id}", web::get().to(job_handler))
+            .route("/events", web::get().to(events_handler))
+            .route("/filename-templates", web::get().to(filename_templates_handler))
+            .route("/probe", web::get().to(probe_handler))
+            .route("/probe", web::post().to(probe_post_handler))
+            .route("/subtitles", web::get().to(subtitles_handler))
+            .route("/subtitles", web::post().to(subtitles_post_handler))
+            .route("/admin/rate-limit", web::get().to(rate_limit_handler))
+            .route("/admin/rate-limit", web::put().to(set_rate_limit_handler))
+            .route("/subscriptions", web::get().to(subscriptions_handler))
+            .route("/subscriptions", web::post().to(subscription_create_handler))
+            .route("/subscriptions/{id}", web::get().to(subscription_handler))
+            .route("/subscriptions/{id}", web::put().to(subscription_update_handler))
+            .route("/subscriptions/{id}", web::delete().to(subscription_delete_handler))
+            .route("/subscriptions/{id}/check", web::post().to(subscription_check_handler))
+            .route("/history", web::get().to(history_handler))
+            .route("/history", web::delete().to(history_prune_handler))
+            .route("/history/{id}", web::delete().to(history_delete_handler))
             .route("/download", web::post().to(download_handler))
             .route("/downloadV2", web::post().to(download_handlerv2))
+            .route("/downloads/batch", web::post().to(batch_download_handler))
             .route("/verify-premium", web::post().to(verify_premium_handler))
     })
     //.bind(("127.0.0.1", port))?
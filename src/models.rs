@@ -1,12 +1,28 @@
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Tytuł zastępczy zapisywany w kolejce, gdy klient nie podał własnego.
+pub(crate) const UNKNOWN_TITLE: &str = "Unknown Title";
+
 #[derive(Serialize)]
 pub struct StatusResponse {
     pub(crate) status: &'static str,
     pub(crate) version: &'static str,
     pub(crate) timestamp: f64,
-    pub(crate) downloads_folder: String,}
+    pub(crate) downloads_folder: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) disk: Option<DiskSpace>,}
+
+/// Miejsce na dysku z folderem pobierania.
+#[derive(Serialize)]
+pub struct DiskSpace {
+    pub(crate) free_bytes: u64,
+    pub(crate) total_bytes: u64,
+    /// kolejka wstrzymana, bo wolnego miejsca jest mniej niż próg `low_space`
+    pub(crate) paused: bool,
+    pub(crate) low_space_threshold: u64,
+}
 
 #[derive(Serialize, Clone)]
 pub struct YtDlpStatus {
@@ -17,7 +33,7 @@ pub struct YtDlpStatus {
     pub(crate) error: Option<String>,
     pub(crate) message: String,}
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct DownloadRequest {
     pub(crate) url: String,
     #[serde(default)]
@@ -32,6 +48,96 @@ pub struct DownloadRequest {
     pub(crate) username: Option<String>,
     #[serde(default)]
     pub(crate) password: Option<String>,
+    /// dodaj mimo duplikatu (odpowiedź wskaże wtedy `duplicate_of`)
+    #[serde(default)]
+    pub(crate) allow_duplicate: bool,
+    /// nadpisuje ustawienie serwera `--download-archive` dla tego zadania
+    #[serde(default)]
+    pub(crate) use_archive: Option<bool>,
+    /// rozwija playlistę/kanał na osobne zadania
+    #[serde(default)]
+    pub(crate) playlist: bool,
+    /// zakres pozycji playlisty w składni yt-dlp, np. `1-10` albo `1,3,5-7`
+    #[serde(default)]
+    pub(crate) playlist_items: Option<String>,
+    /// czas uniksowy, przed którym pobieranie nie wystartuje
+    #[serde(default)]
+    pub(crate) start_after: Option<f64>,
+    /// limit prędkości tego zadania, np. `2M` albo liczba B/s
+    #[serde(default)]
+    pub(crate) limit_rate: Option<RateValue>,
+    /// nazwa szablonu nazwy pliku (`GET /filename-templates`) albo własny szablon yt-dlp
+    #[serde(default)]
+    pub(crate) filename_template: Option<String>,
+    /// postępowanie z istniejącym plikiem o tej samej nazwie (`None` - ustawienie serwera)
+    #[serde(default)]
+    pub(crate) on_collision: Option<CollisionPolicy>,
+    /// napisy do pobrania (`None` - ustawienia serwera)
+    #[serde(default)]
+    pub(crate) subtitles: Option<SubtitleOptions>,
+}
+
+/// Żądanie `POST /downloads/batch` - tablica wpisów albo obiekt ze wspólnymi ustawieniami.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum BatchRequest {
+    Entries(Vec<DownloadRequest>),
+    WithDefaults(Box<BatchDownloadRequest>),
+}
+
+/// Partia wpisów i/lub listy adresów z ustawieniami wspólnymi dla wszystkich pozycji.
+/// Pola podane we wpisie mają pierwszeństwo przed wspólnymi.
+#[derive(Deserialize)]
+pub struct BatchDownloadRequest {
+    #[serde(default)]
+    pub(crate) entries: Vec<DownloadRequest>,
+    /// adresy rozdzielone nowymi liniami (puste linie i `#komentarze` są pomijane)
+    #[serde(default)]
+    pub(crate) urls: Option<String>,
+    #[serde(default)]
+    pub(crate) quality: Option<String>,
+    #[serde(default)]
+    pub(crate) format: Option<String>,
+    #[serde(default)]
+    pub(crate) subfolder: Option<String>,
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    #[serde(default)]
+    pub(crate) allow_duplicate: bool,
+    #[serde(default)]
+    pub(crate) use_archive: Option<bool>,
+    #[serde(default)]
+    pub(crate) start_after: Option<f64>,
+    #[serde(default)]
+    pub(crate) limit_rate: Option<RateValue>,
+    #[serde(default)]
+    pub(crate) filename_template: Option<String>,
+    #[serde(default)]
+    pub(crate) on_collision: Option<CollisionPolicy>,
+    #[serde(default)]
+    pub(crate) subtitles: Option<SubtitleOptions>,
+}
+
+/// Prędkość podana jako liczba B/s albo tekst z przyrostkiem (`500K`, `2M`).
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RateValue {
+    Bytes(u64),
+    Text(String),
+}
+
+impl RateValue {
+    /// Limit w B/s. `Ok(None)` oznacza brak limitu, `Err` - nieczytelną wartość.
+    pub(crate) fn bytes(&self) -> Result<Option<u64>, ()> {
+        match self {
+            RateValue::Bytes(0) => Ok(None),
+            RateValue::Bytes(b) => Ok(Some(*b)),
+            RateValue::Text(t) if t.trim().is_empty() || t.trim() == "0" => Ok(None),
+            RateValue::Text(t) => crate::config::parse_rate(t).map(Some).ok_or(()),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -42,8 +148,32 @@ pub struct DownloadResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) output_path: Option<String>,
+    /// ostateczna ścieżka zapisanego pliku
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) output_file: Option<String>,
     pub(crate) id: Option<u64>,
+    /// ID istniejącego zadania z tym samym filmem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duplicate_of: Option<u64>,
+}
+
+impl DownloadResponse {
+    /// Odpowiedź z samym opisem błędu (bez zadania).
+    pub(crate) fn error(msg: impl Into<String>) -> Self {
+        DownloadResponse {
+            success: false,
+            message: None,
+            error: Some(msg.into()),
+            error_code: None,
+            output_path: None,
+            output_file: None,
+            id: None,
+            duplicate_of: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -56,6 +186,95 @@ pub struct DownloadQueueItem {     pub(crate) url: String,
     pub(crate) username: Option<String>,
     pub(crate) password: Option<String>,
     pub(crate) id: u64,
+    /// liczba nieudanych prób pobrania
+    #[serde(default)]
+    pub(crate) attempt: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_error: Option<String>,
+    /// czas uniksowy, przed którym zadanie nie zostanie ponowione
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) retry_at: Option<f64>,
+    /// archiwum pobranych filmów dla tego zadania (`None` - ustawienie serwera)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) use_archive: Option<bool>,
+    /// ID partii, do której należy zadanie rozwinięte z playlisty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) batch_id: Option<u64>,
+    /// pozycja na playliście (od 1), używana do numerowania plików
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) playlist_index: Option<u32>,
+    /// czas uniksowy, przed którym pobieranie nie wystartuje
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) start_after: Option<f64>,
+    /// limit prędkości zadania w B/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) limit_rate: Option<u64>,
+    /// `title` odczytano z metadanych - służy tylko do wyświetlania, nie do nazwy pliku
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) title_from_metadata: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) thumbnail: Option<String>,
+    /// sprawdzony szablon nazwy pliku (`None` - domyślny szablon serwera)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) filename_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_collision: Option<CollisionPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subtitles: Option<SubtitleOptions>,
+}
+
+impl DownloadQueueItem {
+    /// Najwcześniejszy czas startu wynikający z `start_after` i ponowienia.
+    pub(crate) fn not_before(&self) -> Option<f64> {
+        match (self.start_after, self.retry_at) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Rodzaj błędu pobierania rozpoznany z wyjścia yt-dlp.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    LoginRequired,
+    GeoBlocked,
+    Unavailable,
+    UnsupportedUrl,
+    Network,
+    Cancelled,
+    /// film jest już w kolejce albo został pobrany
+    Duplicate,
+    /// yt-dlp pominął film, bo jest już w archiwum pobranych
+    AlreadyDownloaded,
+    /// plik o tej nazwie już istnieje, a polityka kolizji to `skip`
+    NameCollision,
+    /// za mało wolnego miejsca na dysku
+    InsufficientSpace,
+    DownloadFailed,
+}
+
+impl ErrorCode {
+    /// Błędy, po których ponowienie ma sens (sieć, nierozpoznane awarie yt-dlp).
+    pub(crate) fn is_retryable(self) -> bool {
+        matches!(self, ErrorCode::Network | ErrorCode::DownloadFailed)
+    }
+
+    pub(crate) fn http_status(self) -> u16 {
+        match self {
+            ErrorCode::LoginRequired => 401,
+            ErrorCode::GeoBlocked => 451,
+            ErrorCode::Unavailable => 404,
+            ErrorCode::UnsupportedUrl => 400,
+            ErrorCode::Network => 502,
+            ErrorCode::Cancelled => 409,
+            ErrorCode::Duplicate => 409,
+            ErrorCode::AlreadyDownloaded => 409,
+            ErrorCode::NameCollision => 409,
+            ErrorCode::InsufficientSpace => 507,
+            ErrorCode::DownloadFailed => 500,
+        }
+    }
 }
 
 pub struct JobResult {
@@ -63,7 +282,601 @@ pub struct JobResult {
     pub(crate) http_status: u16,
     pub(crate) message: Option<String>,
     pub(crate) error: Option<String>,
-    pub(crate) output_path: Option<String>, }
+    pub(crate) error_code: Option<ErrorCode>,
+    pub(crate) output_path: Option<String>,
+    pub(crate) output_file: Option<String>,
+    /// co zrobiono, bo plik o tej nazwie już istniał
+    pub(crate) collision: Option<CollisionPolicy>, }
+
+/// Co zrobić, gdy plik o docelowej nazwie już istnieje.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// zostawić istniejący plik, a nowy odrzucić
+    Skip,
+    /// zastąpić istniejący plik nowym
+    Overwrite,
+    /// zapisać nowy plik z przyrostkiem ` (2)`, ` (3)`…
+    Rename,
+}
+
+impl CollisionPolicy {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "skip" => Some(CollisionPolicy::Skip),
+            "overwrite" => Some(CollisionPolicy::Overwrite),
+            "rename" => Some(CollisionPolicy::Rename),
+            _ => None,
+        }
+    }
+}
+
+/// Format zapisywanych napisów (`--convert-subs`).
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Some(SubtitleFormat::Vtt),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// Gdzie trafiają pobrane napisy.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleMode {
+    /// osadzone jako ścieżki napisów w pliku mp4/mkv
+    Embed,
+    /// osobne pliki obok filmu (`Film.pl.srt`)
+    Sidecar,
+}
+
+impl SubtitleMode {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "embed" => Some(SubtitleMode::Embed),
+            "sidecar" | "file" | "files" => Some(SubtitleMode::Sidecar),
+            _ => None,
+        }
+    }
+}
+
+/// Napisy w żądaniu pobrania. Pominięte pola biorą wartość z ustawień serwera.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct SubtitleOptions {
+    /// kody języków w składni yt-dlp, np. `["pl", "en.*"]`; pusta lista wyłącza napisy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) languages: Option<Vec<String>>,
+    /// napisy dodane przez autora
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) manual: Option<bool>,
+    /// napisy generowane automatycznie (np. YouTube)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) automatic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) format: Option<SubtitleFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<SubtitleMode>,
+}
+
+impl JobResult {
+    pub(crate) fn failed(code: ErrorCode, error: String) -> Self {
+        JobResult {
+            success: false,
+            http_status: code.http_status(),
+            message: None,
+            error: Some(error),
+            error_code: Some(code),
+            output_path: None,
+            output_file: None,
+            collision: None,
+        }
+    }
+
+    /// Stan końcowy zadania z tym wynikiem.
+    pub(crate) fn final_state(&self) -> JobState {
+        if self.success {
+            JobState::Completed
+        } else if self.error_code == Some(ErrorCode::AlreadyDownloaded) {
+            JobState::AlreadyDownloaded
+        } else if self.error_code == Some(ErrorCode::NameCollision) {
+            JobState::Skipped
+        } else {
+            JobState::Failed
+        }
+    }
+}
+
+/// Etapy życia zadania pobierania.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Probing,
+    Downloading,
+    Merging,
+    Converting,
+    Completed,
+    /// pominięte - film był już w archiwum pobranych
+    AlreadyDownloaded,
+    /// pominięte - plik o tej nazwie już istniał (inny film mógł mieć ten sam tytuł)
+    Skipped,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    /// Czy zadanie jest już zakończone (stan końcowy).
+    pub(crate) fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Completed
+                | JobState::AlreadyDownloaded
+                | JobState::Skipped
+                | JobState::Failed
+                | JobState::Cancelled
+        )
+    }
+}
+
+/// Postęp pobierania odczytany z wyjścia yt-dlp.
+#[derive(Serialize, Clone)]
+pub struct DownloadProgress {
+    pub(crate) phase: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) downloaded_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) total_bytes: Option<u64>,
+    /// bajty na sekundę
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) speed: Option<f64>,
+    /// sekundy do końca
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) eta: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fragment_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fragment_count: Option<u64>,
+}
+
+impl DownloadProgress {
+    pub(crate) fn new(phase: JobState) -> Self {
+        DownloadProgress {
+            phase,
+            percent: None,
+            downloaded_bytes: None,
+            total_bytes: None,
+            speed: None,
+            eta: None,
+            fragment_index: None,
+            fragment_count: None,
+        }
+    }
+}
+
+/// Informacja wyciągnięta z pojedynczej linii wyjścia yt-dlp.
+pub enum YtDlpEvent {
+    Phase(JobState),
+    Progress(DownloadProgress),
+    /// film pominięty, bo jest w archiwum `--download-archive`
+    AlreadyDownloaded,
+}
+
+#[derive(Serialize, Clone)]
+pub struct JobStatus {
+    pub(crate) id: u64,
+    pub(crate) state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) batch_id: Option<u64>,
+    pub(crate) attempt: u32,
+    pub(crate) url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) thumbnail: Option<String>,
+    pub(crate) created_at: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) started_at: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) finished_at: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) output_file: Option<String>,
+    /// co zrobiono, bo plik o tej nazwie już istniał
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) collision: Option<CollisionPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) progress: Option<DownloadProgress>,
+}
+
+/// Zdarzenia wysyłane do klientów przez `/events` (SSE).
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    JobAdded {
+        id: u64,
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    Progress {
+        id: u64,
+        progress: DownloadProgress,
+    },
+    PhaseChange {
+        id: u64,
+        state: JobState,
+    },
+    Completed {
+        id: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output_file: Option<String>,
+    },
+    AlreadyDownloaded {
+        id: u64,
+    },
+    /// nie zapisano, bo plik o tej nazwie już istniał
+    Skipped {
+        id: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output_file: Option<String>,
+    },
+    /// odczytano prawdziwy tytuł zadania dodanego bez tytułu
+    MetadataResolved {
+        id: u64,
+        title: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thumbnail: Option<String>,
+    },
+    Failed {
+        id: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error_code: Option<ErrorCode>,
+    },
+}
+
+/// Powód, dla którego oczekujące zadanie jeszcze nie wystartowało.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitReason {
+    /// czeka na czas `start_after`
+    StartAfter,
+    /// czeka na ponowienie po błędzie
+    Retry,
+    /// czeka na otwarcie okna pobierania
+    Window,
+    /// kolejka wstrzymana z powodu braku miejsca na dysku
+    LowSpace,
+}
+
+/// Element kolejki razem z bieżącym stanem zadania (odpowiedź `/queue`).
+#[derive(Serialize)]
+pub struct QueueEntry {
+    #[serde(flatten)]
+    pub(crate) item: DownloadQueueItem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) state: Option<JobState>,
+    /// pozycja wśród oczekujących zadań (od 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) position: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) waiting: Option<WaitReason>,
+    /// czas uniksowy, do którego zadanie czeka
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) waiting_until: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) progress: Option<DownloadProgress>,
+}
+
+/// Zakończone zadanie zapisane w historii pobrań.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub(crate) id: u64,
+    pub(crate) url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    /// `completed`, `already_downloaded`, `skipped`, `failed` albo `cancelled`
+    pub(crate) outcome: JobState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) output_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) size_bytes: Option<u64>,
+    /// czas pobierania w sekundach (od startu do zakończenia)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) duration: Option<f64>,
+    pub(crate) created_at: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) started_at: Option<f64>,
+    pub(crate) finished_at: f64,
+    #[serde(default)]
+    pub(crate) attempt: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+}
+
+/// Filtry i stronicowanie `GET /history`.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub(crate) offset: usize,
+    #[serde(default)]
+    pub(crate) limit: Option<usize>,
+    #[serde(default)]
+    pub(crate) outcome: Option<JobState>,
+    /// fragment adresu, tytułu albo nazwy pliku (bez rozróżniania wielkości liter)
+    #[serde(default)]
+    pub(crate) q: Option<String>,
+    /// czas uniksowy - tylko wpisy zakończone od tej chwili
+    #[serde(default)]
+    pub(crate) since: Option<f64>,
+    /// czas uniksowy - tylko wpisy zakończone przed tą chwilą
+    #[serde(default)]
+    pub(crate) until: Option<f64>,
+}
+
+/// Kryteria usuwania wpisów przez `DELETE /history`.
+#[derive(Deserialize)]
+pub struct HistoryPruneQuery {
+    /// czas uniksowy - usuwa wpisy zakończone przed tą chwilą
+    #[serde(default)]
+    pub(crate) before: Option<f64>,
+    #[serde(default)]
+    pub(crate) outcome: Option<JobState>,
+    /// bez innych kryteriów wymagane `all=true`, żeby przypadkiem nie wyczyścić historii
+    #[serde(default)]
+    pub(crate) all: bool,
+}
+
+/// Strona historii (odpowiedź `GET /history`), najnowsze wpisy najpierw.
+#[derive(Serialize)]
+pub struct HistoryPage {
+    pub(crate) total: usize,
+    pub(crate) offset: usize,
+    pub(crate) limit: usize,
+    pub(crate) items: Vec<HistoryEntry>,
+}
+
+/// Wynik `yt-dlp --flat-playlist -J` - interesują nas tylko pozycje playlisty.
+#[derive(Deserialize)]
+pub struct FlatPlaylist {
+    #[serde(rename = "_type", default)]
+    pub(crate) kind: Option<String>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) entries: Vec<Option<FlatPlaylistEntry>>,
+    /// numery pozycji wybranych przez `--playlist-items`, w kolejności `entries`
+    #[serde(default)]
+    pub(crate) requested_entries: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct FlatPlaylistEntry {
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+    #[serde(default)]
+    pub(crate) webpage_url: Option<String>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) playlist_index: Option<u32>,
+    /// ekstraktor i ID filmu - razem tworzą wpis archiwum `--download-archive`
+    #[serde(default)]
+    pub(crate) ie_key: Option<String>,
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+}
+
+/// Wynik dodania jednej pozycji partii (playlisty).
+#[derive(Serialize)]
+pub struct BatchEntryResult {
+    pub(crate) url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) playlist_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duplicate_of: Option<u64>,
+}
+
+/// Odpowiedź na dodanie partii zadań.
+#[derive(Serialize)]
+pub struct BatchResponse {
+    pub(crate) success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) batch_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    /// liczba dodanych zadań
+    pub(crate) added: usize,
+    pub(crate) entries: Vec<BatchEntryResult>,
+}
+
+/// Nowy globalny limit prędkości (`PUT /admin/rate-limit`), `null` lub `0` - bez limitu.
+#[derive(Deserialize)]
+pub struct RateLimitRequest {
+    #[serde(default)]
+    pub(crate) rate_limit: Option<RateValue>,
+}
+
+/// Bieżący globalny limit prędkości.
+#[derive(Serialize)]
+pub struct RateLimitResponse {
+    pub(crate) success: bool,
+    /// łączny limit w B/s
+    pub(crate) rate_limit: Option<u64>,
+    /// limit każdego pobrania - łączny limit podzielony przez liczbę workerów
+    pub(crate) per_worker: Option<u64>,
+    pub(crate) workers: usize,
+}
+
+/// Odpowiedź na anulowanie partii.
+#[derive(Serialize)]
+pub struct BatchCancelResponse {
+    pub(crate) success: bool,
+    pub(crate) batch_id: u64,
+    /// zadania, które zostały anulowane (pozostałe były już zakończone)
+    pub(crate) cancelled: Vec<u64>,
+}
+
+/// Obserwowana playlista lub kanał, sprawdzane cyklicznie w poszukiwaniu nowych filmów.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    pub(crate) id: u64,
+    pub(crate) url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    /// co ile minut sprawdzać nowe pozycje
+    pub(crate) interval_minutes: u64,
+    pub(crate) subfolder: String,
+    pub(crate) quality: String,
+    pub(crate) format_selector: String,
+    /// zakres pozycji do sprawdzania, np. `1-20` dla najnowszych filmów kanału
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) playlist_items: Option<String>,
+    /// szablon nazwy pliku dla nowych pozycji (`None` - domyślny szablon serwera)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) filename_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) password: Option<String>,
+    pub(crate) enabled: bool,
+    pub(crate) created_at: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_checked: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_error: Option<String>,
+    /// liczba zadań dodanych przy ostatnim sprawdzeniu
+    #[serde(default)]
+    pub(crate) last_added: usize,
+    /// partia z ostatnio dodanymi zadaniami
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_batch_id: Option<u64>,
+}
+
+/// Subskrypcja zwracana przez API - bez hasła, które zostaje tylko w pliku subskrypcji.
+#[derive(Serialize)]
+pub struct SubscriptionResponse {
+    pub(crate) id: u64,
+    pub(crate) url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    pub(crate) interval_minutes: u64,
+    pub(crate) subfolder: String,
+    pub(crate) quality: String,
+    pub(crate) format_selector: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) playlist_items: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) filename_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) username: Option<String>,
+    /// czy zapisano hasło (samo hasło nie jest zwracane)
+    pub(crate) has_password: bool,
+    pub(crate) enabled: bool,
+    pub(crate) created_at: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) last_checked: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) last_error: Option<String>,
+    pub(crate) last_added: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) last_batch_id: Option<u64>,
+}
+
+impl From<Subscription> for SubscriptionResponse {
+    fn from(sub: Subscription) -> Self {
+        SubscriptionResponse {
+            id: sub.id,
+            url: sub.url,
+            title: sub.title,
+            interval_minutes: sub.interval_minutes,
+            subfolder: sub.subfolder,
+            quality: sub.quality,
+            format_selector: sub.format_selector,
+            playlist_items: sub.playlist_items,
+            filename_template: sub.filename_template,
+            username: sub.username,
+            has_password: sub.password.is_some(),
+            enabled: sub.enabled,
+            created_at: sub.created_at,
+            last_checked: sub.last_checked,
+            last_error: sub.last_error,
+            last_added: sub.last_added,
+            last_batch_id: sub.last_batch_id,
+        }
+    }
+}
+
+/// Dane subskrypcji z `POST /subscriptions` i `PUT /subscriptions/{id}`.
+/// Przy edycji pominięte pola zostają bez zmian.
+#[derive(Deserialize)]
+pub struct SubscriptionRequest {
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) interval_minutes: Option<u64>,
+    #[serde(default)]
+    pub(crate) subfolder: Option<String>,
+    #[serde(default)]
+    pub(crate) quality: Option<String>,
+    #[serde(default)]
+    pub(crate) format: Option<String>,
+    #[serde(default)]
+    pub(crate) playlist_items: Option<String>,
+    #[serde(default)]
+    pub(crate) filename_template: Option<String>,
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    #[serde(default)]
+    pub(crate) enabled: Option<bool>,
+}
+
+/// Odpowiedź na usunięcie wpisów (historii, subskrypcji).
+#[derive(Serialize)]
+pub struct RemovedResponse {
+    pub(crate) success: bool,
+    pub(crate) removed: usize,
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub(crate) success: bool,
+    pub(crate) error: String,
+}
 
 
 
@@ -76,6 +889,187 @@ pub struct DownloadParams {
     pub(crate) custom_title: Option<String>,
     pub(crate) username: Option<String>,
     pub(crate) password: Option<String>,
+    pub(crate) use_archive: Option<bool>,
+    pub(crate) playlist_index: Option<u32>,
+    pub(crate) limit_rate: Option<u64>,
+    pub(crate) filename_template: Option<String>,
+    pub(crate) on_collision: Option<CollisionPolicy>,
+    pub(crate) subtitles: Option<SubtitleOptions>,
+}
+
+
+/// Parametry `GET /probe?url=` (lub treść `POST /probe`, gdy potrzebne są dane logowania).
+#[derive(Deserialize)]
+pub struct ProbeRequest {
+    pub(crate) url: String,
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+}
+
+/// Surowy wynik `yt-dlp -J` - tylko pola potrzebne do podsumowania.
+#[derive(Deserialize)]
+pub struct YtDlpInfo {
+    #[serde(rename = "_type", default)]
+    pub(crate) kind: Option<String>,
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) uploader: Option<String>,
+    #[serde(default)]
+    pub(crate) channel: Option<String>,
+    #[serde(default)]
+    pub(crate) duration: Option<f64>,
+    #[serde(default)]
+    pub(crate) thumbnail: Option<String>,
+    #[serde(default)]
+    pub(crate) webpage_url: Option<String>,
+    #[serde(default)]
+    pub(crate) extractor_key: Option<String>,
+    #[serde(default)]
+    pub(crate) playlist_count: Option<u64>,
+    #[serde(default)]
+    pub(crate) entries: Option<Vec<serde::de::IgnoredAny>>,
+    #[serde(default)]
+    pub(crate) filesize: Option<u64>,
+    #[serde(default)]
+    pub(crate) filesize_approx: Option<u64>,
+    #[serde(default)]
+    pub(crate) formats: Vec<YtDlpFormat>,
+    /// napisy według kodu języka
+    #[serde(default)]
+    pub(crate) subtitles: HashMap<String, Vec<YtDlpSubtitle>>,
+    #[serde(default)]
+    pub(crate) automatic_captions: HashMap<String, Vec<YtDlpSubtitle>>,
 }
 
+/// Jeden wariant napisów w wyniku `yt-dlp -J`.
+#[derive(Deserialize)]
+pub struct YtDlpSubtitle {
+    #[serde(default)]
+    pub(crate) ext: Option<String>,
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+}
 
+#[derive(Deserialize)]
+pub struct YtDlpFormat {
+    #[serde(default)]
+    pub(crate) format_id: Option<String>,
+    #[serde(default)]
+    pub(crate) ext: Option<String>,
+    #[serde(default)]
+    pub(crate) format_note: Option<String>,
+    #[serde(default)]
+    pub(crate) resolution: Option<String>,
+    #[serde(default)]
+    pub(crate) width: Option<u32>,
+    #[serde(default)]
+    pub(crate) height: Option<u32>,
+    #[serde(default)]
+    pub(crate) fps: Option<f64>,
+    #[serde(default)]
+    pub(crate) vcodec: Option<String>,
+    #[serde(default)]
+    pub(crate) acodec: Option<String>,
+    #[serde(default)]
+    pub(crate) tbr: Option<f64>,
+    #[serde(default)]
+    pub(crate) filesize: Option<u64>,
+    #[serde(default)]
+    pub(crate) filesize_approx: Option<u64>,
+}
+
+/// Podsumowanie metadanych adresu zwracane przez `/probe`.
+#[derive(Serialize, Clone)]
+pub struct ProbeInfo {
+    pub(crate) url: String,
+    pub(crate) id: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) uploader: Option<String>,
+    /// długość w sekundach
+    pub(crate) duration: Option<f64>,
+    pub(crate) thumbnail: Option<String>,
+    pub(crate) webpage_url: Option<String>,
+    pub(crate) extractor: Option<String>,
+    pub(crate) is_playlist: bool,
+    pub(crate) playlist_count: Option<u64>,
+    /// szacowany rozmiar formatu wybranego domyślnie przez yt-dlp (w bajtach)
+    pub(crate) estimated_size: Option<u64>,
+    pub(crate) formats: Vec<ProbeFormat>,
+    pub(crate) subtitles: Vec<SubtitleTrack>,
+}
+
+/// Dostępne napisy w jednym języku.
+#[derive(Serialize, Clone)]
+pub struct SubtitleTrack {
+    /// kod języka do użycia w `subtitles.languages`
+    pub(crate) language: String,
+    pub(crate) name: Option<String>,
+    /// generowane automatycznie (wymagają `subtitles.automatic`)
+    pub(crate) automatic: bool,
+    /// formaty udostępniane przez serwis (srt/vtt powstaną z każdego)
+    pub(crate) formats: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ProbeFormat {
+    pub(crate) format_id: String,
+    pub(crate) ext: Option<String>,
+    pub(crate) note: Option<String>,
+    pub(crate) resolution: Option<String>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) fps: Option<f64>,
+    /// `None`, gdy format nie zawiera obrazu
+    pub(crate) vcodec: Option<String>,
+    /// `None`, gdy format nie zawiera dźwięku
+    pub(crate) acodec: Option<String>,
+    /// średni bitrate w KiB/s
+    pub(crate) tbr: Option<f64>,
+    /// rozmiar w bajtach - dokładny albo szacowany (`filesize_approx: true`)
+    pub(crate) filesize: Option<u64>,
+    pub(crate) filesize_approx: bool,
+}
+
+#[derive(Serialize)]
+pub struct ProbeResponse {
+    pub(crate) success: bool,
+    /// wynik pochodzi z pamięci podręcznej
+    pub(crate) cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+    #[serde(flatten)]
+    pub(crate) info: Option<ProbeInfo>,
+}
+
+/// Odpowiedź `GET /subtitles` - napisy dostępne dla adresu.
+#[derive(Serialize)]
+pub struct SubtitlesResponse {
+    pub(crate) success: bool,
+    pub(crate) cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    /// języki napisów dodanych przez autora
+    pub(crate) languages: Vec<String>,
+    /// języki napisów generowanych automatycznie
+    pub(crate) automatic_languages: Vec<String>,
+    pub(crate) tracks: Vec<SubtitleTrack>,
+}
+
+/// Odpowiedź `GET /filename-templates`.
+#[derive(Serialize)]
+pub struct FilenameTemplatesResponse {
+    /// domyślny szablon serwera (nazwa albo sam szablon)
+    pub(crate) default: String,
+    pub(crate) templates: std::collections::BTreeMap<String, String>,
+}
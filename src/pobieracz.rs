@@ -1,54 +1,477 @@
 use std::fs;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use actix_web::web;
-use tokio::sync::mpsc;
-use crate::models::{DownloadParams, JobResult};
-use crate::{log_error, log_info, pobieracz, AppState, DownloadJob};
-use crate::dodatkowe_funkcje::{save_queue_to_file,parse_ytdlp_output_line};
+use tokio::sync::oneshot;
+use crate::models::{BatchEntryResult, BatchResponse, CollisionPolicy, DownloadParams, DownloadQueueItem, ErrorCode, FlatPlaylist, UNKNOWN_TITLE, JobResult, JobState, YtDlpEvent};
+use crate::{disk, filename_template, jobs, sanitize, subtitles, log_error, log_info, pobieracz, AppState, DownloadJob, QUEUE_LEN};
+use crate::dodatkowe_funkcje::{classify_ytdlp_error, current_unix_time_f64, download_archive_path, params_from_queue_item, save_queue_to_file, parse_ytdlp_output_line, PROGRESS_TEMPLATE};
 use crate::setup::{ffmpeg_available, get_ffmpeg, YTDLP_PATH};
+use crate::sites::{normalize_url, site_key};
+use crate::sanitize::SanitizeOptions;
 
 
 
 
 
-pub(crate) async fn download_worker_loop(
-    mut rx: mpsc::Receiver<DownloadJob>,
-    app_state: web::Data<AppState>,
-) {
-    while let Some(job) = rx.recv().await {
+/// Uruchamia pulę workerów pobierania (`ServerConfig::workers`) czytających ze wspólnej kolejki.
+pub(crate) async fn download_worker_loop(app_state: web::Data<AppState>) {
+    let workers = app_state.config.workers;
+    log_info(&format!("👷 Liczba równoległych pobierań: {workers}"));
+
+    let handles: Vec<_> = (1..=workers)
+        .map(|n| tokio::spawn(download_worker(n, app_state.clone())))
+        .collect();
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+}
+
+async fn download_worker(worker_id: usize, app_state: web::Data<AppState>) {
+    loop {
+        let Some(job) = next_job(&app_state) else {
+            // czekamy na nowe zadanie albo zwolnione miejsce; co sekundę sprawdzamy
+            // ponownie, bo odstępy między startami mijają bez żadnego powiadomienia
+            let _ = tokio::time::timeout(Duration::from_secs(1), app_state.dispatch.notified()).await;
+            continue;
+        };
+
         let id = job.id;
+        let site = site_key(&job.params.url);
+        log_info(&format!("👷 Worker {worker_id} bierze zadanie #{id} ({site})"));
         let params = job.params.clone();
-        let res = pobieracz::run_download_and_convert(&params, id);
+        jobs::set_job_state(&app_state, id, JobState::Probing);
+
+        let res = match disk::ensure_space(&app_state, id, &params).await {
+            Err(res) => res,
+            Ok(()) => {
+                let state = app_state.clone();
+                tokio::task::spawn_blocking(move || {
+                    pobieracz::run_download_and_convert(&params, id, &state)
+                })
+                .await
+                .unwrap_or_else(|e| {
+                    let msg = format!("Wątek pobierania #{id} zakończył się błędem: {e}");
+                    log_error(&msg);
+                    JobResult::failed(ErrorCode::DownloadFailed, msg)
+                })
+            }
+        };
+        app_state.sites.release(&site);
+        // pobrany film zajął miejsce - może trzeba wstrzymać kolejkę
+        disk::refresh_pause(&app_state);
+
+        if let Some((attempt, retry_at)) = schedule_retry(&app_state, id, &res) {
+            log_info(&format!(
+                "🔁 Zadanie #{id}: próba {attempt}/{} nieudana, ponowienie za {:.0} s",
+                app_state.config.max_retries + 1,
+                retry_at - current_unix_time_f64()
+            ));
+            jobs::requeue_job(&app_state, id, attempt, &res);
+            let mut job = job;
+            job.not_before = Some(retry_at);
+            app_state.pending.lock().unwrap().push_back(job);
+            app_state.dispatch.notify_waiters();
+            continue;
+        }
+
+        jobs::finish_job(&app_state, id, &res);
 
-        // if job.resp_tx.send(res).is_err() {
-        //     log_error(&format!(
-        //         "❌ Błąd wątku pobierania #{id}: nie udało się odesłać wyniku"
-        //     ));
-        // }
+        // wynik czeka tylko synchroniczne /download - brak odbiorcy nie jest błędem
+        let _ = job.resp_tx.send(res);
 
         // Usuwanie z kolejki po zakończeniu
+        {
+            let mut queue = app_state.queue.lock().unwrap();
+            // queue.retain(|item| item.url != job.params.url);
+            queue.retain(|item| item.id != id);
+            save_queue_to_file(&queue);
+        }
+        QUEUE_LEN.fetch_sub(1, Ordering::SeqCst);
+        app_state.dispatch.notify_waiters();
+    }
+}
+
+/// Wybiera pierwsze oczekujące zadanie, które może wystartować w ramach limitów serwisów.
+/// Zadania z serwisów, które trzeba przyhamować, czekają, a pozostałe idą dalej.
+fn next_job(app_state: &AppState) -> Option<DownloadJob> {
+    let mut pending = app_state.pending.lock().unwrap();
+
+    let (cancelled, waiting): (VecDeque<_>, VecDeque<_>) = pending
+        .drain(..)
+        .partition(|job| jobs::is_cancelled(app_state, job.id));
+    *pending = waiting;
+
+    for job in cancelled {
+        log_info(&format!("⏭️ Pomijam anulowane zadanie #{}", job.id));
+        QUEUE_LEN.fetch_sub(1, Ordering::SeqCst);
+        let _ = job.resp_tx.send(cancelled_result());
+    }
+
+    let now = current_unix_time_f64();
+    // poza oknem pobierania zadania czekają w kolejce
+    if !app_state.config.in_download_window(now) {
+        return None;
+    }
+    // przy braku miejsca na dysku też
+    if app_state.low_space.load(Ordering::SeqCst) {
+        return None;
+    }
+    let index = pending.iter().position(|job| {
+        job.not_before.is_none_or(|t| t <= now)
+            && app_state.sites.try_acquire(&site_key(&job.params.url))
+    })?;
+    pending.remove(index)
+}
+
+/// Po przejściowym błędzie zapisuje w kolejce kolejną próbę i wyznacza czas ponowienia.
+/// Zwraca numer nieudanej próby i czas ponowienia albo `None`, jeśli zadanie się kończy.
+fn schedule_retry(app_state: &AppState, id: u64, res: &JobResult) -> Option<(u32, f64)> {
+    if res.success || !res.error_code.is_some_and(|code| code.is_retryable()) {
+        return None;
+    }
+
+    let mut queue = app_state.queue.lock().unwrap();
+    // anulowane zadanie nie jest już w kolejce
+    let item = queue.iter_mut().find(|item| item.id == id)?;
+    if item.attempt >= app_state.config.max_retries {
+        return None;
+    }
+
+    item.attempt += 1;
+    let retry_at = current_unix_time_f64() + app_state.config.retry_delay(item.attempt) as f64;
+    item.last_error = res.error.clone();
+    item.retry_at = Some(retry_at);
+    let attempt = item.attempt;
+    save_queue_to_file(&queue);
+
+    Some((attempt, retry_at))
+}
+
+/// Dodaje zadanie do kolejki workerów. Zwraca liczbę niezakończonych zadań.
+pub(crate) fn submit_job(app_state: &AppState, job: DownloadJob) -> usize {
+    app_state.pending.lock().unwrap().push_back(job);
+    app_state.dispatch.notify_waiters();
+    QUEUE_LEN.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+/// Nowe ID zadania albo partii - obie numeracje dzielą jeden licznik.
+pub(crate) fn next_job_id(app_state: &AppState) -> u64 {
+    app_state
+        .job_counter
+        .fetch_add(1, Ordering::SeqCst)
+        .wrapping_add(1)
+}
+
+/// Część globalnego limitu prędkości przypadająca na jednego workera. Każde pobranie
+/// dostaje ten sam stały udział, więc nawet przy wszystkich zajętych workerach suma
+/// nie przekracza limitu.
+pub(crate) fn rate_limit_share(app_state: &AppState) -> Option<u64> {
+    let global = app_state.rate_limit.load(Ordering::SeqCst);
+    (global > 0).then(|| (global / app_state.config.workers as u64).max(1))
+}
+
+/// Limit dla startującego zadania - mniejszy z limitu zadania i udziału w limicie globalnym.
+fn effective_rate_limit(app_state: &AppState, job_limit: Option<u64>) -> Option<u64> {
+    match (job_limit, rate_limit_share(app_state)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Wynik dodawania pobierania do kolejki.
+pub(crate) enum Enqueued {
+    Added {
+        id: u64,
+        /// zadanie z tym samym filmem, jeśli duplikat został dopuszczony
+        duplicate_of: Option<u64>,
+        result: oneshot::Receiver<JobResult>,
+    },
+    /// ten sam film już czeka, pobiera się albo został pobrany (ID istniejącego zadania)
+    Duplicate(u64),
+}
+
+/// Nadaje zadaniu ID, zapisuje je w kolejce i przekazuje workerom.
+pub(crate) fn enqueue_download(app_state: &AppState, item: DownloadQueueItem, allow_duplicate: bool) -> Enqueued {
+    enqueue_downloads(app_state, vec![(item, allow_duplicate)])
+        .pop()
+        .expect("jedno zadanie daje jeden wynik")
+}
+
+/// Dodaje zadania do kolejki naraz - jedna blokada i jeden zapis pliku kolejki.
+/// Sprawdzenie duplikatów i dopisanie do kolejki odbywa się pod tą samą blokadą,
+/// więc dwa równoczesne żądania z tym samym adresem nie przejdą obu.
+/// Drugi element pary pozwala dodać zadanie mimo duplikatu.
+pub(crate) fn enqueue_downloads(app_state: &AppState, items: Vec<(DownloadQueueItem, bool)>) -> Vec<Enqueued> {
+    let mut results = Vec::with_capacity(items.len());
+    let mut added = Vec::new();
+
+    {
         let mut queue = app_state.queue.lock().unwrap();
-        // queue.retain(|item| item.url != job.params.url);
-        queue.retain(|item| item.id != id);
-        save_queue_to_file(&queue);
+        for (mut item, allow_duplicate) in items {
+            let duplicate_of = find_duplicate(app_state, &queue, &item.url);
+            if let (Some(existing), false) = (duplicate_of, allow_duplicate) {
+                results.push(Enqueued::Duplicate(existing));
+                continue;
+            }
+
+            item.id = next_job_id(app_state);
+            jobs::register_job(app_state, &item);
+            queue.push(item.clone());
+
+            if let Some(existing) = duplicate_of {
+                log_info(&format!("⚠️ Zadanie #{} to duplikat zadania #{existing}", item.id));
+            }
+
+            let (resp_tx, resp_rx) = oneshot::channel::<JobResult>();
+            results.push(Enqueued::Added {
+                id: item.id,
+                duplicate_of,
+                result: resp_rx,
+            });
+            added.push((item, resp_tx));
+        }
+        if !added.is_empty() {
+            save_queue_to_file(&queue);
+        }
+    }
+
+    for (item, resp_tx) in added {
+        submit_job(app_state, DownloadJob {
+            id: item.id,
+            params: params_from_queue_item(&item),
+            not_before: item.not_before(),
+            resp_tx,
+        });
+    }
+    results
+}
+
+/// Wynik dodania jednej pozycji partii.
+pub(crate) fn batch_entry(url: String, playlist_index: Option<u32>, enqueued: Enqueued) -> BatchEntryResult {
+    match enqueued {
+        Enqueued::Added { id, duplicate_of, .. } => BatchEntryResult {
+            url,
+            id: Some(id),
+            playlist_index,
+            error: None,
+            error_code: None,
+            duplicate_of,
+        },
+        Enqueued::Duplicate(existing) => BatchEntryResult {
+            url,
+            id: None,
+            playlist_index,
+            error: Some(format!("Ten film jest już w kolejce albo został pobrany (zadanie #{existing})")),
+            error_code: Some(ErrorCode::Duplicate),
+            duplicate_of: Some(existing),
+        },
+    }
+}
+
+/// Pobiera listę pozycji playlisty/kanału (`yt-dlp --flat-playlist -J`) bez pobierania filmów.
+/// `items` to opcjonalny zakres w składni `--playlist-items`.
+pub(crate) fn expand_playlist(
+    url: &str,
+    items: Option<&str>,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<FlatPlaylist, (ErrorCode, String)> {
+    let mut cmd = Command::new(YTDLP_PATH.as_str());
+    cmd.args(["--flat-playlist", "-J", "--no-warnings"]);
+    if let Some(items) = items.filter(|i| !i.trim().is_empty()) {
+        cmd.args(["--playlist-items", items.trim()]);
+    }
+    if let (Some(u), Some(p)) = (username, password) {
+        cmd.args(["--username", u, "--password", p]);
+    }
+    cmd.arg(url);
+
+    log_info(&format!("📜 Rozwijam playlistę: {url}"));
+    let output = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| (ErrorCode::DownloadFailed, format!("Nie udało się uruchomić yt-dlp: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let msg = stderr
+            .lines()
+            .rev()
+            .find(|l| l.trim_start().starts_with("ERROR"))
+            .unwrap_or("yt-dlp nie odczytał playlisty")
+            .trim()
+            .to_string();
+        log_error(&format!("❌ Nie udało się rozwinąć playlisty: {msg}"));
+        return Err((classify_ytdlp_error(&stderr), msg));
+    }
+
+    serde_json::from_slice::<FlatPlaylist>(&output.stdout).map_err(|e| {
+        let msg = format!("Nieprawidłowa odpowiedź yt-dlp dla playlisty: {e}");
+        log_error(&msg);
+        (ErrorCode::DownloadFailed, msg)
+    })
+}
+
+/// Dodaje każdą pozycję playlisty jako osobne zadanie, wszystkie w jednej partii.
+/// Duplikaty i pozycje bez adresu są pomijane i zgłaszane w odpowiedzi.
+pub(crate) fn enqueue_playlist(app_state: &AppState, template: DownloadQueueItem, playlist: FlatPlaylist, allow_duplicate: bool) -> BatchResponse {
+    let batch_id = next_job_id(app_state);
+    let mut invalid = Vec::new();
+    let mut items = Vec::new();
+
+    for (i, entry) in playlist.entries.into_iter().enumerate() {
+        let Some(entry) = entry else {
+            continue;
+        };
+        let playlist_index = entry
+            .playlist_index
+            .or_else(|| playlist.requested_entries.get(i).copied())
+            .unwrap_or(i as u32 + 1);
+        let url = entry
+            .url
+            .or(entry.webpage_url)
+            .filter(|u| u.starts_with("http://") || u.starts_with("https://"));
+
+        let Some(url) = url else {
+            invalid.push(BatchEntryResult {
+                url: String::new(),
+                id: None,
+                playlist_index: Some(playlist_index),
+                error: Some("Brak adresu pozycji playlisty".into()),
+                error_code: Some(ErrorCode::UnsupportedUrl),
+                duplicate_of: None,
+            });
+            continue;
+        };
+
+        let item = DownloadQueueItem {
+            url,
+            title: Some(entry.title.unwrap_or_else(|| UNKNOWN_TITLE.into())),
+            batch_id: Some(batch_id),
+            playlist_index: Some(playlist_index),
+            ..template.clone()
+        };
+        items.push((item, allow_duplicate));
+    }
+
+    let keys: Vec<(String, Option<u32>)> = items
+        .iter()
+        .map(|(item, _)| (item.url.clone(), item.playlist_index))
+        .collect();
+    let mut entries: Vec<BatchEntryResult> = keys
+        .into_iter()
+        .zip(enqueue_downloads(app_state, items))
+        .map(|((url, index), enqueued)| batch_entry(url, index, enqueued))
+        .collect();
+    entries.extend(invalid);
+    entries.sort_by_key(|e| e.playlist_index);
+
+    let added = entries.iter().filter(|e| e.id.is_some()).count();
+    log_info(&format!(
+        "📜 Partia #{batch_id}: dodano {added} z {} pozycji playlisty",
+        entries.len()
+    ));
+
+    BatchResponse {
+        success: added > 0,
+        batch_id: (added > 0).then_some(batch_id),
+        title: playlist.title,
+        added,
+        entries,
     }
 }
 
+/// Szuka oczekującego, trwającego albo ukończonego zadania z tym samym filmem.
+fn find_duplicate(app_state: &AppState, queue: &[DownloadQueueItem], url: &str) -> Option<u64> {
+    let key = normalize_url(url);
+
+    if let Some(item) = queue.iter().find(|item| normalize_url(&item.url) == key) {
+        return Some(item.id);
+    }
+
+    app_state
+        .history
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        // `Skipped` się nie liczy - plik o tej nazwie mógł należeć do innego filmu
+        .find(|e| matches!(e.outcome, JobState::Completed | JobState::AlreadyDownloaded) && normalize_url(&e.url) == key)
+        .map(|e| e.id)
+}
+
+
+/// Wznawia zadania wczytane z pliku kolejki - przekazuje je workerom w zapisanej kolejności.
+pub(crate) fn restore_queue(app_state: &AppState) {
+    let items = app_state.queue.lock().unwrap().clone();
+    if items.is_empty() {
+        return;
+    }
+
+    log_info(&format!("♻️ Wznawiam {} zadań z poprzedniej sesji", items.len()));
+
+    for item in items {
+        let params = params_from_queue_item(&item);
+
+        if let Err(e) = fs::create_dir_all(&params.output_path) {
+            log_error(&format!(
+                "📂 Nie udało się utworzyć folderu {:?} dla zadania #{}: {e}",
+                params.output_path, item.id
+            ));
+        }
+
+        // nikt nie czeka na wynik wznowionego zadania
+        let (resp_tx, _resp_rx) = oneshot::channel::<JobResult>();
+        let job = DownloadJob {
+            id: item.id,
+            params,
+            not_before: item.not_before(),
+            resp_tx,
+        };
+
+        submit_job(app_state, job);
+        log_info(&format!("♻️ Wznowiono pobieranie #{}: {}", item.id, item.url));
+    }
+}
 
 
-pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) -> JobResult {
+pub(crate) fn run_download_and_convert(
+    params: &DownloadParams,
+    job_id: u64,
+    app_state: &web::Data<AppState>,
+) -> JobResult {
 
 
     let has_premium = params.username.is_some() && params.password.is_some();
     let output_path = params.output_path.clone();
+    let on_collision = params.on_collision.unwrap_or(app_state.config.on_collision);
+
+    // yt-dlp pisze do osobnego folderu zadania, a gotowy plik trafia na miejsce dopiero
+    // na końcu - dzięki temu kolizje nazw rozstrzyga serwer, a nie yt-dlp
+    let staging = match StagingDir::create(&output_path, job_id) {
+        Ok(dir) => dir,
+        Err(e) => {
+            let msg = format!("Nie udało się utworzyć folderu roboczego: {e}");
+            log_error(&msg);
+            return JobResult::failed(ErrorCode::DownloadFailed, msg);
+        }
+    };
 
     let mut cmd: Vec<String> = vec![YTDLP_PATH.clone()];
 
     // dodajemy --newline, żeby postęp był wypisywany od razu
     cmd.push("--newline".into());
+    // postęp w formacie czytelnym dla parse_ytdlp_output_line
+    cmd.push("--progress-template".into());
+    cmd.push(PROGRESS_TEMPLATE.into());
 
     let quality_arg = match params.quality.as_str() {
         "best" => "bestvideo+bestaudio/best",
@@ -89,7 +512,7 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
         "--postprocessor-args".into(),
         "ffmpeg:-movflags +faststart".into(),
         "--concurrent-fragments".into(),
-        "10".into(),
+        app_state.config.concurrent_fragments.to_string(),
         "--retries".into(),
         "10".into(),
         "--fragment-retries".into(),
@@ -101,24 +524,65 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
         "--no-write-info-json".into(),
         "--no-write-thumbnail".into(),
         "--no-write-description".into(),
-        "--no-write-auto-sub".into(),
-        "--no-write-sub".into(),
         "--no-embed-thumbnail".into(),
         "--add-metadata".into(),
         "--no-warnings".into(),
     ]);
 
-    let output_template = if let Some(title) = &params.custom_title {
-        let clean = clean_filename(title);
-        log_info(&format!("📋 Używam własnego tytułu: {clean}"));
-        output_path.join(format!("{clean}.%(ext)s"))
-    } else {
-        output_path.join("%(title)s.%(ext)s")
+    let subtitle_settings = subtitles::settings(params.subtitles.as_ref(), &app_state.config);
+    let embed_subtitles = match &subtitle_settings {
+        Some(settings) => {
+            log_info(&format!("💬 Napisy: {}", settings.languages.join(", ")));
+            let args = subtitles::ytdlp_args(settings, &target_format, ffmpeg_available());
+            let embed = args.iter().any(|a| a == "--embed-subs");
+            cmd.extend(args);
+            embed
+        }
+        None => {
+            cmd.extend(["--no-write-auto-sub".into(), "--no-write-sub".into()]);
+            false
+        }
     };
+
+    let template = params
+        .filename_template
+        .clone()
+        .or_else(|| filename_template::resolve(&app_state.config, None).ok())
+        .unwrap_or_else(|| "%(title)s".into());
+    let sanitize_opts = SanitizeOptions::from_config(&app_state.config);
+    let custom_title = params.custom_title.as_deref().map(|t| sanitize::sanitize(t, &sanitize_opts));
+    if let Some(clean) = &custom_title {
+        log_info(&format!("📋 Używam własnego tytułu: {clean}"));
+    }
+    let output_template = staging.0.join(filename_template::output_template(
+        &template,
+        custom_title.as_deref(),
+        params.playlist_index,
+        sanitize_opts.max_bytes(),
+    ));
     let output_template_str = output_template.to_string_lossy().to_string();
     cmd.push("-o".into());
     cmd.push(output_template_str);
 
+    // yt-dlp zapisuje tu ostateczną ścieżkę pliku - przy kilku workerach w jednym
+    // folderze nie da się jej zgadywać po dacie modyfikacji
+    let filepath_file = std::env::temp_dir().join(format!("vda_job_{job_id}.path"));
+    let _ = fs::remove_file(&filepath_file);
+    cmd.push("--print-to-file".into());
+    cmd.push("after_move:filepath".into());
+    cmd.push(filepath_file.to_string_lossy().to_string());
+
+    if let Some(rate) = effective_rate_limit(app_state, params.limit_rate) {
+        log_info(&format!("🐢 Limit prędkości zadania #{job_id}: {rate} B/s"));
+        cmd.push("--limit-rate".into());
+        cmd.push(rate.to_string());
+    }
+
+    if params.use_archive.unwrap_or(app_state.config.download_archive) {
+        cmd.push("--download-archive".into());
+        cmd.push(download_archive_path().to_string_lossy().to_string());
+    }
+
     if has_premium {
         if let (Some(u), Some(p)) = (&params.username, &params.password) {
             cmd.push("--username".into());
@@ -144,60 +608,95 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
         Err(e) => {
             let msg = format!("Nie udało się uruchomić yt-dlp: {e}");
             log_error(&msg);
-            return JobResult {
-                success: false,
-                http_status: 500,
-                message: None,
-                error: Some(msg),
-                output_path: None,
-            };
+            return JobResult::failed(ErrorCode::DownloadFailed, msg);
         }
     };
 
     let stdout = child.stdout.take().unwrap();
     let stderr = child.stderr.take().unwrap();
 
-    let downloaded_file = Arc::new(Mutex::new(None));
-    let df_clone1 = downloaded_file.clone();
-    let df_clone2 = downloaded_file.clone();
+    let output_files = Arc::new(Mutex::new(OutputFiles::default()));
+    let df_clone1 = output_files.clone();
+    let df_clone2 = output_files.clone();
+    let state_clone1 = app_state.clone();
+    let state_clone2 = app_state.clone();
 
 
     /////duplikacja to nie błąd!!!!!!
     let stdout_thread = std::thread::spawn(move || {
         let reader = BufReader::new(stdout);
         for line in reader.lines().flatten() {
-            let mut df = df_clone1.lock().unwrap();
-            parse_ytdlp_output_line(&line, &mut df);
+            handle_ytdlp_line(&state_clone1, job_id, &line, &df_clone1);
         }
     });
 
     let stderr_thread = std::thread::spawn(move || {
         let reader = BufReader::new(stderr);
         for line in reader.lines().flatten() {
-            let mut df = df_clone2.lock().unwrap();
-            parse_ytdlp_output_line(&line, &mut df);
+            handle_ytdlp_line(&state_clone2, job_id, &line, &df_clone2);
         }
     });
 
-    // Czekamy na zakończenie yt-dlp
-    let _status = child.wait().expect("Błąd oczekiwania na yt-dlp");
+    // Czekamy na zakończenie yt-dlp (albo na anulowanie zadania)
+    let status = wait_or_cancel(&mut child, app_state, job_id);
 
     // Czekamy na wątki stdout/stderr
     let _ = stdout_thread.join();
     let _ = stderr_thread.join();
 
+    let exit_status = match status {
+        Ok(Some(s)) if s.success() && output_files.lock().unwrap().already_downloaded => {
+            log_info(&format!("📚 Zadanie #{job_id} pominięte - film jest już w archiwum"));
+            return JobResult::failed(
+                ErrorCode::AlreadyDownloaded,
+                "Film został już wcześniej pobrany (archiwum pobranych)".into(),
+            );
+        }
+        Ok(Some(s)) => s,
+        Ok(None) => return cancelled_result(),
+        Err(e) => {
+            let msg = format!("Błąd oczekiwania na yt-dlp: {e}");
+            log_error(&msg);
+            return JobResult::failed(ErrorCode::DownloadFailed, msg);
+        }
+    };
+
     // Pobranie finalnej wartości pobranego pliku
-    let downloaded_file = downloaded_file.lock().unwrap().clone();
+    let printed_file = fs::read_to_string(&filepath_file)
+        .ok()
+        .and_then(|data| data.lines().last().map(|l| PathBuf::from(l.trim())))
+        .filter(|p| !p.as_os_str().is_empty());
+    let _ = fs::remove_file(&filepath_file);
+    let downloaded_file = printed_file.or_else(|| output_files.lock().unwrap().last.clone());
+
 
+    // ścieżka pliku zgłoszonego przez yt-dlp (względna względem folderu roboczego)
+    let downloaded_file = downloaded_file.map(|p| if p.is_absolute() { p } else { staging.0.join(p) });
+    let mut final_file: Option<PathBuf> = downloaded_file.clone().filter(|p| p.exists());
+
+    if !exit_status.success() || final_file.is_none() {
+        let errors = output_files.lock().unwrap().errors.clone();
+
+        let (code, msg) = if exit_status.success() {
+            (ErrorCode::DownloadFailed, "yt-dlp nie utworzył pliku wynikowego".to_string())
+        } else {
+            let code = classify_ytdlp_error(&errors.join("\n"));
+            let msg = errors
+                .last()
+                .cloned()
+                .unwrap_or_else(|| format!("yt-dlp zakończył się błędem ({exit_status})"));
+            (code, msg)
+        };
+        log_error(&format!("❌ Pobieranie #{job_id} nie powiodło się ({code:?}): {msg}"));
+        // niedokończone pliki znikają razem z folderem roboczym
+        return JobResult::failed(code, msg);
+    }
 
     ////
     let mut actual_downloaded_file: Option<PathBuf> = None;
 
     if needs_conversion {
-        if let Some(mut p) = downloaded_file.clone() {
-            if !p.is_absolute() {
-                p = output_path.join(p);
-            }
+        if let Some(p) = downloaded_file.clone() {
             if p.exists() {
                 actual_downloaded_file = Some(p.clone());
                 log_info(&format!(
@@ -207,9 +706,10 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
             }
         }
 
+        // w folderze roboczym są tylko pliki tego zadania, więc zgadywanie jest bezpieczne
         if actual_downloaded_file.is_none() {
             log_info("🔍 Wyszukiwanie pobranego pliku MP4 do konwersji...");
-            if let Some(p) = find_latest_mp4_in_dir(&output_path) {
+            if let Some(p) = find_latest_mp4_in_dir(&staging.0) {
                 log_info(&format!(
                     "Znaleziono plik do konwersji: {}",
                     p.to_string_lossy()
@@ -229,6 +729,7 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
                 let base = src.with_extension("");
                 let output_file = base.with_extension(&target_format);
 
+                jobs::set_job_state(app_state, job_id, JobState::Converting);
                 log_info(&format!(
                     "🔄 Rozpoczynam konwersję {} do {}",
                     src.to_string_lossy(),
@@ -252,30 +753,41 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
                         output_file.to_string_lossy().into(),
                     ]
                 } else {
-                    vec![
+                    let mut args: Vec<String> = vec![
                         ffmpeg_path.clone(),
                         "-i".into(),
                         src.to_string_lossy().into(),
                         "-c".into(),
                         "copy".into(),
+                    ];
+                    // osadzone napisy przechodzą do MKV/WebM razem z obrazem i dźwiękiem
+                    if embed_subtitles {
+                        args.extend(subtitles::ffmpeg_args(&target_format));
+                    }
+                    args.extend([
                         "-movflags".into(),
                         "+faststart".into(),
                         "-y".into(),
                         output_file.to_string_lossy().into(),
-                    ]
+                    ]);
+                    args
                 };
 
                 log_info(&format!("ffmpeg cmd: {:?}", ffmpeg_cmd));
 
+                // stderr ffmpeg nie jest czytany, więc nie może trafić do potoku
                 let status = Command::new(&ffmpeg_cmd[0])
                     .args(&ffmpeg_cmd[1..])
                     .stdout(Stdio::null())
-                    .stderr(Stdio::piped())
-                    .status();
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .and_then(|mut ffmpeg| wait_or_cancel(&mut ffmpeg, app_state, job_id));
 
                 match status {
-                    Ok(s) if s.success() => {
+                    Ok(None) => return cancelled_result(),
+                    Ok(Some(s)) if s.success() => {
                         log_info("✅ Konwersja zakończona pomyślnie!");
+                        final_file = Some(output_file.clone());
                         if let Err(e) = fs::remove_file(src) {
                             log_error(&format!(
                                 "Nie udało się usunąć oryginalnego pliku: {e}"
@@ -290,7 +802,7 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
                             ));
                         }
                     }
-                    Ok(_) => {
+                    Ok(Some(_)) => {
                         log_error("⚠️ Konwersja nie powiodła się");
                         log_info("ℹ️ Plik pozostał w formacie MP4");
                     }
@@ -305,28 +817,215 @@ pub(crate) fn run_download_and_convert(params: &DownloadParams, job_id: u64) ->
         }
     }
 
+    let Some(file) = final_file else {
+        return JobResult::failed(ErrorCode::DownloadFailed, "yt-dlp nie utworzył pliku wynikowego".into());
+    };
+    let (target, collision) = match place_output(&staging.0, &output_path, &file, on_collision, &sanitize_opts.filesystem_only()) {
+        Ok(placed) => placed,
+        Err(msg) => {
+            log_error(&format!("❌ {msg}"));
+            return JobResult::failed(ErrorCode::DownloadFailed, msg);
+        }
+    };
+
+    if collision == Some(CollisionPolicy::Skip) {
+        log_info(&format!("⏭️ Plik {} już istnieje - pomijam nowy", target.to_string_lossy()));
+        return JobResult {
+            output_path: Some(output_path.to_string_lossy().to_string()),
+            output_file: Some(target.to_string_lossy().to_string()),
+            collision,
+            ..JobResult::failed(
+                ErrorCode::NameCollision,
+                format!("Plik o tej nazwie już istnieje: {}", target.to_string_lossy()),
+            )
+        };
+    }
+
     log_info(&format!(
         "📁 Zapisano do: {}",
-        output_path.to_string_lossy()
+        target.to_string_lossy()
     ));
+    if subtitle_settings.is_some() {
+        subtitles::move_sidecars(&file, &target);
+    }
 
     JobResult {
         success: true,
         http_status: 200,
         message: Some("Pobieranie zakończone pomyślnie".into()),
         error: None,
+        error_code: None,
         output_path: Some(output_path.to_string_lossy().to_string()),
+        output_file: Some(target.to_string_lossy().to_string()),
+        collision,
+    }
+}
+
+/// Folder roboczy zadania (`.vda-<id>` w folderze docelowym), usuwany razem
+/// z resztkami pobierania, gdy zadanie się kończy - także po błędzie i anulowaniu.
+struct StagingDir(PathBuf);
+
+impl StagingDir {
+    fn create(output_path: &Path, job_id: u64) -> std::io::Result<Self> {
+        let dir = output_path.join(format!(".vda-{job_id}"));
+        // pozostałość po przerwanym wcześniej zadaniu
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(StagingDir(dir))
     }
 }
 
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log_error(&format!("Nie udało się usunąć folderu roboczego {:?}: {e}", self.0));
+            }
+        }
+    }
+}
 
+/// Przenosi gotowy plik z folderu roboczego na miejsce docelowe zgodnie z polityką kolizji,
+/// dopasowując po drodze nazwy folderów i pliku do systemu plików ([`sanitize`]).
+/// Zwraca ostateczną ścieżkę i politykę, jeśli trzeba było jej użyć. Przy `Skip` ścieżka
+/// wskazuje istniejący plik, a nowy zostaje w folderze roboczym (i znika razem z nim).
+fn place_output(
+    staging: &Path,
+    output_path: &Path,
+    file: &Path,
+    policy: CollisionPolicy,
+    sanitize_opts: &SanitizeOptions,
+) -> Result<(PathBuf, Option<CollisionPolicy>), String> {
+    // dwa workery nie mogą jednocześnie uznać tej samej nazwy za wolną
+    static PLACE_LOCK: Mutex<()> = Mutex::new(());
+    let _guard = PLACE_LOCK.lock().unwrap();
+
+    let relative = file
+        .strip_prefix(staging)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| PathBuf::from(file.file_name().unwrap_or_default()));
+    // nazwy z metadanych (tytuł, kanał) też muszą pasować do systemu plików;
+    // transliteracja do ASCII dotyczy tylko własnych tytułów
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let target = components.iter().enumerate().fold(output_path.to_path_buf(), |path, (i, name)| {
+        if i + 1 == components.len() {
+            path.join(sanitize::sanitize_file_name(name, sanitize_opts))
+        } else {
+            path.join(sanitize::sanitize(name, sanitize_opts))
+        }
+    });
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Nie udało się utworzyć folderu {:?}: {e}", parent))?;
+    }
+
+    let (target, collision) = if !target.exists() {
+        (target, None)
+    } else {
+        match policy {
+            CollisionPolicy::Skip => return Ok((target, Some(CollisionPolicy::Skip))),
+            CollisionPolicy::Overwrite => {
+                // na Windows rename nie zastępuje istniejącego pliku
+                let _ = fs::remove_file(&target);
+                (target, Some(CollisionPolicy::Overwrite))
+            }
+            CollisionPolicy::Rename => {
+                let stem = target.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                let ext = target.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+                let renamed = (2..)
+                    .map(|n| target.with_file_name(format!("{stem} ({n}){ext}")))
+                    .find(|candidate| !candidate.exists())
+                    .unwrap_or(target);
+                (renamed, Some(CollisionPolicy::Rename))
+            }
+        }
+    };
+
+    fs::rename(file, &target).map_err(|e| format!("Nie udało się przenieść pliku do {:?}: {e}", target))?;
+    Ok((target, collision))
+}
+
+
+
+/// Pliki i błędy zgłoszone przez yt-dlp w trakcie pobierania.
+#[derive(Default)]
+struct OutputFiles {
+    /// ostatni plik docelowy (po scaleniu formatów - plik wynikowy)
+    last: Option<PathBuf>,
+    /// linie `ERROR:` do rozpoznania przyczyny niepowodzenia
+    errors: Vec<String>,
+    /// yt-dlp pominął film zapisany w archiwum pobranych
+    already_downloaded: bool,
+}
+
+/// Przekazuje linię wyjścia yt-dlp do parsera i aktualizuje stan zadania.
+fn handle_ytdlp_line(
+    app_state: &AppState,
+    job_id: u64,
+    line: &str,
+    output_files: &Mutex<OutputFiles>,
+) {
+    let event = {
+        let mut files = output_files.lock().unwrap();
+        let event = parse_ytdlp_output_line(line, &mut files.last);
+        if line.trim_start().starts_with("ERROR") {
+            files.errors.push(line.trim().to_string());
+        }
+        event
+    };
+
+    match event {
+        Some(YtDlpEvent::Phase(state)) => {
+            jobs::set_job_state(app_state, job_id, state);
+        }
+        Some(YtDlpEvent::Progress(progress)) => {
+            jobs::update_progress(app_state, job_id, progress);
+        }
+        Some(YtDlpEvent::AlreadyDownloaded) => {
+            output_files.lock().unwrap().already_downloaded = true;
+        }
+        None => {}
+    }
+}
+
+/// Czeka na zakończenie procesu, sprawdzając co chwilę, czy zadanie nie zostało anulowane.
+/// Zwraca `Ok(None)`, jeśli proces został zabity z powodu anulowania.
+fn wait_or_cancel(child: &mut Child, app_state: &AppState, job_id: u64) -> std::io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if jobs::is_cancelled(app_state, job_id) {
+            log_info(&format!("🛑 Przerywam proces zadania #{job_id}"));
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn cancelled_result() -> JobResult {
+    JobResult::failed(ErrorCode::Cancelled, "Pobieranie zostało anulowane".into())
+}
 
 fn find_latest_mp4_in_dir(dir: &Path) -> Option<PathBuf> {
     let mut candidates: Vec<(PathBuf, std::time::SystemTime)> = Vec::new();
+    collect_mp4_files(dir, &mut candidates);
+    candidates.sort_by_key(|(_, t)| *t);
+    candidates.pop().map(|(p, _)| p)
+}
+
+/// Zbiera pliki MP4 z folderu i jego podfolderów (szablon nazwy może tworzyć podfoldery).
+fn collect_mp4_files(dir: &Path, candidates: &mut Vec<(PathBuf, std::time::SystemTime)>) {
     if let Ok(entries) = fs::read_dir(dir) {
         for e in entries.flatten() {
             let path = e.path();
-            if path
+            if path.is_dir() {
+                collect_mp4_files(&path, candidates);
+            } else if path
                 .extension()
                 .map(|ext| ext.eq_ignore_ascii_case("mp4"))
                 .unwrap_or(false)
@@ -339,47 +1038,4 @@ fn find_latest_mp4_in_dir(dir: &Path) -> Option<PathBuf> {
             }
         }
     }
-    candidates.sort_by_key(|(_, t)| *t);
-    candidates.pop().map(|(p, _)| p)
-}
-
-fn clean_filename(title: &str) -> String {
-    // mapowanie polskich znaków i innych na ASCII
-    let translit = |c: char| match c {
-        'ą' | 'Ą' => 'a',
-        'ć' | 'Ć' => 'c',
-        'ę' | 'Ę' => 'e',
-        'ł' | 'Ł' => 'l',
-        'ń' | 'Ń' => 'n',
-        'ó' | 'Ó' => 'o',
-        'ś' | 'Ś' => 's',
-        'ż' | 'Ż' | 'ź' | 'Ź' => 'z',
-        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
-        'è' | 'é' | 'ê' | 'ë' => 'e',
-        'ì' | 'í' | 'î' | 'ï' => 'i',
-        'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
-        'ù' | 'ú' | 'û' | 'ü' => 'u',
-        'ý' | 'ÿ' => 'y',
-        _ => c,
-    };
-
-    let mut s: String = title
-        .chars()
-        .map(|c| match c {
-            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
-            c => translit(c),
-        })
-        .collect();
-
-    if s.len() > 100 {
-        s.truncate(100);
-    }
-
-    s = s.trim_matches(&['_', ' ', '.'][..]).to_string();
-
-    if s.is_empty() {
-        "Unknown_Video".into()
-    } else {
-        s
-    }
 }
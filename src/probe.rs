@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use actix_web::web;
+use once_cell::sync::Lazy;
+use tokio::sync::Semaphore;
+use crate::dodatkowe_funkcje::{classify_ytdlp_error, current_unix_time_f64};
+use crate::models::{ErrorCode, ProbeFormat, ProbeInfo, YtDlpInfo, UNKNOWN_TITLE};
+use crate::setup::YTDLP_PATH;
+use crate::sites::normalize_url;
+use crate::{jobs, log_error, log_info, subtitles, AppState};
+
+/// Jak długo (w sekundach) wynik sondy jest ważny w pamięci podręcznej.
+const CACHE_TTL: f64 = 600.0;
+
+/// Wyniki sond według adresu i danych logowania ([`cache_key`]): (czas pobrania, podsumowanie).
+static PROBE_CACHE: Lazy<Mutex<HashMap<String, (f64, ProbeInfo)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Ile sond w tle (uzupełnianie tytułów) może działać naraz - partia setek adresów
+/// nie powinna uruchomić setek procesów yt-dlp.
+static BACKGROUND_SLOTS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(2));
+
+/// Zamienia surowy wynik `yt-dlp -J` na podsumowanie. Pomija storyboardy (miniatury klatek).
+fn summarize(url: &str, info: YtDlpInfo) -> ProbeInfo {
+    let is_playlist = matches!(info.kind.as_deref(), Some("playlist" | "multi_video"));
+    let formats = info
+        .formats
+        .into_iter()
+        .filter(|f| f.format_note.as_deref() != Some("storyboard") && f.ext.as_deref() != Some("mhtml"))
+        .filter_map(|f| {
+            let codec = |c: Option<String>| c.filter(|c| c != "none");
+            Some(ProbeFormat {
+                format_id: f.format_id?,
+                ext: f.ext,
+                note: f.format_note,
+                resolution: f.resolution,
+                width: f.width,
+                height: f.height,
+                fps: f.fps,
+                vcodec: codec(f.vcodec),
+                acodec: codec(f.acodec),
+                tbr: f.tbr,
+                filesize_approx: f.filesize.is_none() && f.filesize_approx.is_some(),
+                filesize: f.filesize.or(f.filesize_approx),
+            })
+        })
+        .collect();
+
+    ProbeInfo {
+        url: url.to_string(),
+        id: info.id,
+        title: info.title,
+        uploader: info.uploader.or(info.channel),
+        duration: info.duration,
+        thumbnail: info.thumbnail,
+        webpage_url: info.webpage_url,
+        extractor: info.extractor_key,
+        is_playlist,
+        playlist_count: info.playlist_count.or(info.entries.map(|e| e.len() as u64)),
+        estimated_size: info.filesize.or(info.filesize_approx),
+        formats,
+        subtitles: subtitles::tracks(info.subtitles, info.automatic_captions),
+    }
+}
+
+/// Odczytuje metadane adresu (`yt-dlp -J --no-download`) bez pobierania filmu.
+/// Playlisty nie są rozwijane (`--flat-playlist`), więc sonda kanału też jest szybka.
+pub(crate) fn probe(url: &str, username: Option<&str>, password: Option<&str>) -> Result<ProbeInfo, (ErrorCode, String)> {
+    let mut cmd = Command::new(YTDLP_PATH.as_str());
+    cmd.args(["-J", "--no-download", "--flat-playlist", "--no-warnings"]);
+    if let (Some(u), Some(p)) = (username, password) {
+        cmd.args(["--username", u, "--password", p]);
+    }
+    cmd.arg(url);
+
+    log_info(&format!("🔍 Sprawdzam metadane: {url}"));
+    let output = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| (ErrorCode::DownloadFailed, format!("Nie udało się uruchomić yt-dlp: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let msg = stderr
+            .lines()
+            .rev()
+            .find(|l| l.trim_start().starts_with("ERROR"))
+            .unwrap_or("yt-dlp nie odczytał metadanych")
+            .trim()
+            .to_string();
+        log_error(&format!("❌ Nie udało się odczytać metadanych: {msg}"));
+        return Err((classify_ytdlp_error(&stderr), msg));
+    }
+
+    let info = serde_json::from_slice::<YtDlpInfo>(&output.stdout).map_err(|e| {
+        let msg = format!("Nieprawidłowa odpowiedź yt-dlp: {e}");
+        log_error(&msg);
+        (ErrorCode::DownloadFailed, msg)
+    })?;
+    Ok(summarize(url, info))
+}
+
+/// Klucz pamięci podręcznej. Konto Premium widzi inne formaty niż gość, więc wynik
+/// jest wspólny tylko dla tego samego adresu i tych samych danych logowania.
+fn cache_key(url: &str, username: Option<&str>, password: Option<&str>) -> String {
+    let mut hasher = DefaultHasher::new();
+    password.hash(&mut hasher);
+    format!("{}\n{}\n{:x}", normalize_url(url), username.unwrap_or_default(), hasher.finish())
+}
+
+/// Ważny wynik sondy z pamięci podręcznej - bez uruchamiania yt-dlp.
+pub(crate) fn cached(url: &str, username: Option<&str>, password: Option<&str>) -> Option<ProbeInfo> {
+    let cache = PROBE_CACHE.lock().unwrap();
+    let (fetched, info) = cache.get(&cache_key(url, username, password))?;
+    (current_unix_time_f64() - fetched < CACHE_TTL).then(|| ProbeInfo { url: url.to_string(), ..info.clone() })
+}
+
+/// Jak [`probe`], ale najpierw zagląda do pamięci podręcznej.
+/// Zwraca podsumowanie i informację, czy pochodzi z pamięci.
+pub(crate) async fn probe_cached(
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+) -> Result<(ProbeInfo, bool), (ErrorCode, String)> {
+    if let Some(info) = cached(&url, username.as_deref(), password.as_deref()) {
+        return Ok((info, true));
+    }
+
+    let key = cache_key(&url, username.as_deref(), password.as_deref());
+    let now = current_unix_time_f64();
+
+    let info = tokio::task::spawn_blocking(move || probe(&url, username.as_deref(), password.as_deref()))
+        .await
+        .map_err(|e| (ErrorCode::DownloadFailed, format!("Błąd odczytu metadanych: {e}")))??;
+
+    let mut cache = PROBE_CACHE.lock().unwrap();
+    cache.retain(|_, (fetched, _)| now - *fetched < CACHE_TTL);
+    cache.insert(key, (current_unix_time_f64(), info.clone()));
+    Ok((info, false))
+}
+
+/// W tle odczytuje prawdziwy tytuł i miniaturę zadania dodanego bez tytułu
+/// i uzupełnia nimi element kolejki (widoczne w `/queue`). Zadania z tytułem są pomijane.
+pub(crate) fn resolve_title_later(app_state: &web::Data<AppState>, id: u64) {
+    let untitled = move |app_state: &AppState| {
+        app_state
+            .queue
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|item| item.id == id && item.title.as_deref().is_none_or(|t| t == UNKNOWN_TITLE))
+            .map(|item| (item.url.clone(), item.username.clone(), item.password.clone()))
+    };
+    if untitled(app_state).is_none() {
+        return;
+    }
+
+    let app_state = app_state.clone();
+    tokio::spawn(async move {
+        let Ok(_slot) = BACKGROUND_SLOTS.acquire().await else {
+            return;
+        };
+        // zadanie mogło się w międzyczasie zakończyć albo zostać anulowane
+        if jobs::job_status(&app_state, id).is_none_or(|job| job.state.is_finished()) {
+            return;
+        }
+        let Some((url, username, password)) = untitled(&app_state) else {
+            return;
+        };
+
+        match probe_cached(url, username, password).await {
+            Ok((info, _)) => {
+                if let Some(title) = info.title.filter(|t| !t.trim().is_empty()) {
+                    jobs::set_metadata(&app_state, id, title, info.thumbnail);
+                }
+            }
+            Err((_, msg)) => log_error(&format!("🏷️ Nie udało się odczytać tytułu zadania #{id}: {msg}")),
+        }
+    });
+}
@@ -0,0 +1,306 @@
+use crate::config::ServerConfig;
+
+/// Nazwa pliku, gdy po oczyszczeniu nic nie zostało.
+const FALLBACK_NAME: &str = "Unknown_Video";
+
+/// Bajty zostawione na to, co trafi do nazwy po tytule: numer pozycji playlisty,
+/// ` (2)` przy kolizji, rozszerzenie i pliki tymczasowe yt-dlp (`.f137.webm.part`).
+const RESERVED_BYTES: usize = 48;
+
+/// Nazwy urządzeń, których Windows nie pozwala użyć jako nazwy pliku (także z rozszerzeniem).
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// System plików folderu pobierania - decyduje o limicie długości nazwy
+/// i o tym, czy da się w niej zapisać emoji.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Filesystem {
+    /// ext4/btrfs - 255 bajtów, dowolne znaki
+    Native,
+    /// zaszyfrowany folder współdzielony DSM (eCryptfs) - tylko 143 bajty
+    Encrypted,
+    /// udział SMB - 255 bajtów, ale znaki spoza BMP (emoji) psują się po stronie klienta
+    Smb,
+}
+
+impl Filesystem {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "native" | "ext4" | "btrfs" => Some(Filesystem::Native),
+            "encrypted" | "ecryptfs" => Some(Filesystem::Encrypted),
+            "smb" | "cifs" => Some(Filesystem::Smb),
+            _ => None,
+        }
+    }
+
+    /// Maksymalna długość jednego składnika ścieżki w bajtach.
+    fn name_limit(self) -> usize {
+        match self {
+            Filesystem::Native | Filesystem::Smb => 255,
+            Filesystem::Encrypted => 143,
+        }
+    }
+
+    fn stores_emoji(self) -> bool {
+        self != Filesystem::Smb
+    }
+}
+
+/// Jak oczyszczać nazwy plików - z `VDA_FILENAME_UNICODE` i `VDA_FILESYSTEM`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SanitizeOptions {
+    /// zostawić litery spoza ASCII zamiast je transliterować
+    pub(crate) keep_unicode: bool,
+    pub(crate) filesystem: Filesystem,
+}
+
+impl SanitizeOptions {
+    pub(crate) fn from_config(config: &ServerConfig) -> Self {
+        SanitizeOptions { keep_unicode: config.filename_unicode, filesystem: config.filesystem }
+    }
+
+    /// Te same reguły systemu plików, ale bez transliteracji do ASCII - dla nazw nadanych
+    /// przez yt-dlp, które (w przeciwieństwie do własnych tytułów) zachowują oryginalne litery.
+    pub(crate) fn filesystem_only(self) -> Self {
+        SanitizeOptions { keep_unicode: true, ..self }
+    }
+
+    /// Limit długości tytułu w bajtach - z miejscem na dopiski z [`RESERVED_BYTES`].
+    pub(crate) fn max_bytes(&self) -> usize {
+        self.filesystem.name_limit() - RESERVED_BYTES
+    }
+}
+
+/// Czy znak to emoji albo jego składnik (modyfikator, selektor wariantu, keycap, tag flagi).
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF   // piktogramy, emotikony, flagi, karty, symbole dodatkowe
+        | 0x2600..=0x27BF   // różne symbole i dingbaty (☀, ✨)
+        | 0x2B00..=0x2BFF   // strzałki i symbole (⭐, ⬆)
+        | 0xFE0E | 0xFE0F   // selektory wariantu tekst/emoji
+        | 0x20E3            // keycap (1️⃣)
+        | 0xE0020..=0xE007F // tagi flag regionalnych
+    )
+}
+
+/// Znaki kierunku tekstu - pozwalają ukryć prawdziwe rozszerzenie (`txt.exe` jako `exe.txt`).
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Najdłuższy początek `s` mieszczący się w `max` bajtach, ucięty na granicy znaku.
+pub(crate) fn truncate_bytes(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let end = (0..=max).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0);
+    &s[..end]
+}
+
+/// Usuwa emoji i znaki sterujące, w razie potrzeby transliteruje do ASCII
+/// i zamienia znaki niedozwolone w Windows na `_`.
+fn clean_chars(name: &str, opts: &SanitizeOptions) -> String {
+    let strip_emoji = !opts.keep_unicode || !opts.filesystem.stores_emoji();
+    let mut out = String::with_capacity(name.len());
+    let mut after_emoji = false;
+
+    for c in name.chars() {
+        if c.is_control() {
+            // tabulatory i nowe linie rozdzielały słowa
+            if c.is_whitespace() {
+                out.push(' ');
+            }
+            continue;
+        }
+        if is_bidi_control(c) {
+            continue;
+        }
+        if strip_emoji {
+            // ZWJ łączy emoji w sekwencje (👨‍👩‍👧), ale w innych pismach jest potrzebny
+            if is_emoji(c) || (c == '\u{200D}' && after_emoji) {
+                after_emoji = true;
+                continue;
+            }
+            after_emoji = false;
+            if !opts.filesystem.stores_emoji() && c > '\u{FFFF}' {
+                continue;
+            }
+        }
+
+        if opts.keep_unicode || c.is_ascii() {
+            out.push(c);
+        } else if let Some(ascii) = deunicode::deunicode_char(c) {
+            out.push_str(ascii);
+        }
+    }
+
+    // transliteracja też może dać `/` (np. z `⁄`), więc zamiana na końcu
+    out.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Scala ciągi spacji (zostają po usuniętych emoji) i obcina spacje, kropki i `_`
+/// z obu końców - Windows nie obsługuje nazw kończących się kropką lub spacją.
+fn tidy(name: &str) -> String {
+    name.split(' ')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(['_', ' ', '.'])
+        .to_string()
+}
+
+/// Dokleja `_` do nazw zarezerwowanych w Windows (`CON` → `CON_`, `nul.txt` → `nul_.txt`).
+fn avoid_reserved(name: String) -> String {
+    let base_len = name.find('.').unwrap_or(name.len());
+    let base = name[..base_len].trim_end();
+    if WINDOWS_RESERVED.iter().any(|r| r.eq_ignore_ascii_case(base)) {
+        format!("{}_{}", &name[..base.len()], &name[base.len()..])
+    } else {
+        name
+    }
+}
+
+/// Zamienia tytuł na bezpieczną nazwę pliku (bez rozszerzenia) dla docelowego systemu plików.
+pub(crate) fn sanitize(title: &str, opts: &SanitizeOptions) -> String {
+    let cleaned = tidy(&clean_chars(title, opts));
+    let name = tidy(truncate_bytes(&cleaned, opts.max_bytes()));
+    if name.is_empty() {
+        return FALLBACK_NAME.into();
+    }
+    avoid_reserved(name)
+}
+
+/// Jak [`sanitize`], ale zachowuje rozszerzenie pliku (np. nazwa nadana przez yt-dlp).
+pub(crate) fn sanitize_file_name(file_name: &str, opts: &SanitizeOptions) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() && ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            format!("{}.{}", sanitize(stem, opts), ext.to_lowercase())
+        }
+        _ => sanitize(file_name, opts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(keep_unicode: bool, filesystem: Filesystem) -> SanitizeOptions {
+        SanitizeOptions { keep_unicode, filesystem }
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        assert_eq!(truncate_bytes("ząb", 2), "z");
+        assert_eq!(truncate_bytes("ząb", 3), "zą");
+        assert_eq!(truncate_bytes("日本語", 4), "日");
+        assert_eq!(truncate_bytes("abc", 10), "abc");
+
+        // dawny `String::truncate(100)` tu panikował
+        let title = "ż".repeat(300);
+        let name = sanitize(&title, &opts(true, Filesystem::Native));
+        assert_eq!(name.len(), 206);
+        assert!(name.chars().all(|c| c == 'ż'));
+    }
+
+    #[test]
+    fn respects_filesystem_byte_limit() {
+        let title = "a".repeat(400);
+        assert_eq!(sanitize(&title, &opts(false, Filesystem::Native)).len(), 255 - RESERVED_BYTES);
+        assert_eq!(sanitize(&title, &opts(false, Filesystem::Encrypted)).len(), 143 - RESERVED_BYTES);
+
+        // po ucięciu nie zostaje spacja ani kropka na końcu
+        let title = format!("{} końcówka", "a".repeat(94));
+        assert_eq!(sanitize(&title, &opts(true, Filesystem::Encrypted)), "a".repeat(94));
+    }
+
+    #[test]
+    fn transliterates_to_ascii_by_default() {
+        let ascii = opts(false, Filesystem::Native);
+        assert_eq!(sanitize("Zażółć gęślą jaźń", &ascii), "Zazolc gesla jazn");
+        assert_eq!(sanitize("Ærøskøbing – Straße", &ascii), "AEroskobing - Strasse");
+        assert_eq!(sanitize("Привет мир", &ascii), "Privet mir");
+    }
+
+    #[test]
+    fn keeps_unicode_when_enabled() {
+        let unicode = opts(true, Filesystem::Native);
+        assert_eq!(sanitize("Zażółć gęślą jaźń", &unicode), "Zażółć gęślą jaźń");
+        assert_eq!(sanitize("東京の夜", &unicode), "東京の夜");
+    }
+
+    #[test]
+    fn replaces_windows_invalid_characters() {
+        let o = opts(true, Filesystem::Native);
+        assert_eq!(sanitize("a<b>c:d\"e/f\\g|h?i*j", &o), "a_b_c_d_e_f_g_h_i_j");
+        // `⁄` (ukośnik ułamkowy) po transliteracji staje się `/`
+        assert_eq!(sanitize("1⁄2 ceny", &opts(false, Filesystem::Native)), "1_2 ceny");
+    }
+
+    #[test]
+    fn handles_windows_reserved_names() {
+        let o = opts(false, Filesystem::Native);
+        assert_eq!(sanitize("CON", &o), "CON_");
+        assert_eq!(sanitize("nul", &o), "nul_");
+        assert_eq!(sanitize("com1.part2", &o), "com1_.part2");
+        assert_eq!(sanitize("Console", &o), "Console");
+        assert_eq!(sanitize_file_name("aux.mp4", &o), "aux_.mp4");
+    }
+
+    #[test]
+    fn strips_trailing_dots_and_spaces() {
+        let o = opts(true, Filesystem::Native);
+        assert_eq!(sanitize("Koniec...", &o), "Koniec");
+        assert_eq!(sanitize("  __Tytuł . ", &o), "Tytuł");
+        assert_eq!(sanitize("...", &o), FALLBACK_NAME);
+    }
+
+    #[test]
+    fn removes_control_characters() {
+        let o = opts(true, Filesystem::Native);
+        assert_eq!(sanitize("Linia\npierwsza\tdruga", &o), "Linia pierwsza druga");
+        assert_eq!(sanitize("a\u{0}b\u{7f}c", &o), "abc");
+        assert_eq!(sanitize("film\u{202E}4pm.exe", &o), "film4pm.exe");
+    }
+
+    #[test]
+    fn removes_emoji_on_smb() {
+        let smb = opts(true, Filesystem::Smb);
+        assert_eq!(sanitize("Koty 😂 na ⭐ wakacjach ✨", &smb), "Koty na wakacjach");
+        assert_eq!(sanitize("Rodzina 👨‍👩‍👧 i flaga 🇵🇱", &smb), "Rodzina i flaga");
+        assert_eq!(sanitize("Zażółć 🎉", &smb), "Zażółć");
+        // inne znaki spoza BMP też nie przejdą przez SMB
+        assert_eq!(sanitize("Znak 𝄞 nutowy", &smb), "Znak nutowy");
+        assert_eq!(sanitize("🔥🔥🔥", &smb), FALLBACK_NAME);
+    }
+
+    #[test]
+    fn keeps_emoji_where_supported() {
+        assert_eq!(sanitize("Koty 😂", &opts(true, Filesystem::Native)), "Koty 😂");
+        // w trybie ASCII emoji znikają zamiast zamieniać się w słowa
+        assert_eq!(sanitize("Koty 😂", &opts(false, Filesystem::Native)), "Koty");
+        // ZWJ poza sekwencją emoji zostaje (np. w pismach indyjskich)
+        assert_eq!(sanitize("क्\u{200D}ष", &opts(true, Filesystem::Smb)), "क्\u{200D}ष");
+    }
+
+    #[test]
+    fn filesystem_only_keeps_letters() {
+        let o = opts(false, Filesystem::Smb).filesystem_only();
+        assert_eq!(sanitize_file_name("Żółć 東京 😂.mp4", &o), "Żółć 東京.mp4");
+        assert_eq!(sanitize_file_name("nul.mp4", &o), "nul_.mp4");
+    }
+
+    #[test]
+    fn keeps_file_extension() {
+        let o = opts(false, Filesystem::Smb);
+        assert_eq!(sanitize_file_name("Zażółć 🎉.MP4", &o), "Zazolc.mp4");
+        assert_eq!(sanitize_file_name("Wersja 2.0", &o), "Wersja 2.0");
+        assert_eq!(sanitize_file_name(".mp4", &o), "mp4");
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+use actix_web::web;
+use crate::dodatkowe_funkcje::{current_unix_time_f64, data_file_path, download_archive_path, downloads_folder, is_safe_subfolder};
+use crate::models::{DownloadQueueItem, FlatPlaylist, Subscription, SubscriptionRequest};
+use crate::pobieracz::{enqueue_playlist, expand_playlist};
+use crate::config::ServerConfig;
+use crate::{filename_template, log_error, log_info, AppState};
+
+static SUBSCRIPTIONS_FILE: &str = "subscriptions.json";
+
+/// Domyślny i najkrótszy dozwolony odstęp między sprawdzeniami (w minutach).
+const DEFAULT_INTERVAL: u64 = 360;
+const MIN_INTERVAL: u64 = 5;
+
+/// Co ile sekund pętla w tle szuka subskrypcji do sprawdzenia.
+const POLL_SECS: u64 = 60;
+
+pub(crate) fn load_subscriptions() -> Vec<Subscription> {
+    let path = data_file_path(SUBSCRIPTIONS_FILE);
+
+    match fs::read_to_string(&path) {
+        Ok(data) => match serde_json::from_str::<Vec<Subscription>>(&data) {
+            Ok(subs) => subs,
+            Err(e) => {
+                log_error(&format!("Nie udało się odczytać subskrypcji JSON z {:?}: {e}", path));
+                vec![]
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+        Err(e) => {
+            log_error(&format!("Nie udało się wczytać pliku {:?}: {e}", path));
+            vec![]
+        }
+    }
+}
+
+fn save_subscriptions(subs: &[Subscription]) {
+    let json = match serde_json::to_string_pretty(subs) {
+        Ok(j) => j,
+        Err(e) => {
+            log_error(&format!("Błąd serializacji subskrypcji do JSON: {e}"));
+            return;
+        }
+    };
+
+    if let Err(e) = fs::write(data_file_path(SUBSCRIPTIONS_FILE), json) {
+        log_error(&format!("Nie udało się zapisać subskrypcji: {e}"));
+    }
+}
+
+fn validate(sub: &Subscription, config: &ServerConfig) -> Result<(), String> {
+    if !(sub.url.starts_with("http://") || sub.url.starts_with("https://")) {
+        return Err("URL musi zaczynać się od http:// lub https://".into());
+    }
+    if !is_safe_subfolder(&sub.subfolder) {
+        return Err("Nieprawidłowa nazwa podfolderu".into());
+    }
+    if let Some(template) = &sub.filename_template {
+        filename_template::resolve(config, Some(template))?;
+    }
+    Ok(())
+}
+
+/// Nadpisuje pola subskrypcji wartościami podanymi w żądaniu.
+fn apply_request(sub: &mut Subscription, req: SubscriptionRequest) {
+    if let Some(url) = req.url {
+        sub.url = url.trim().to_string();
+    }
+    if let Some(title) = req.title {
+        sub.title = Some(title).filter(|t| !t.trim().is_empty());
+    }
+    if let Some(minutes) = req.interval_minutes {
+        sub.interval_minutes = minutes.max(MIN_INTERVAL);
+    }
+    if let Some(subfolder) = req.subfolder {
+        sub.subfolder = subfolder;
+    }
+    if let Some(quality) = req.quality {
+        sub.quality = quality;
+    }
+    if let Some(format) = req.format {
+        sub.format_selector = format;
+    }
+    if let Some(items) = req.playlist_items {
+        sub.playlist_items = Some(items).filter(|i| !i.trim().is_empty());
+    }
+    if let Some(template) = req.filename_template {
+        sub.filename_template = Some(template).filter(|t| !t.trim().is_empty());
+    }
+    if let Some(username) = req.username {
+        sub.username = Some(username).filter(|u| !u.is_empty());
+    }
+    if let Some(password) = req.password {
+        sub.password = Some(password).filter(|p| !p.is_empty());
+    }
+    if let Some(enabled) = req.enabled {
+        sub.enabled = enabled;
+    }
+}
+
+pub(crate) fn list(app_state: &AppState) -> Vec<Subscription> {
+    app_state.subscriptions.lock().unwrap().clone()
+}
+
+pub(crate) fn get(app_state: &AppState, id: u64) -> Option<Subscription> {
+    app_state.subscriptions.lock().unwrap().iter().find(|s| s.id == id).cloned()
+}
+
+pub(crate) fn create(app_state: &AppState, req: SubscriptionRequest) -> Result<Subscription, String> {
+    let mut sub = Subscription {
+        id: 0,
+        url: String::new(),
+        title: None,
+        interval_minutes: DEFAULT_INTERVAL,
+        subfolder: String::new(),
+        quality: app_state.config.default_quality.clone(),
+        format_selector: app_state.config.default_format.clone(),
+        playlist_items: None,
+        filename_template: None,
+        username: None,
+        password: None,
+        enabled: true,
+        created_at: current_unix_time_f64(),
+        last_checked: None,
+        last_error: None,
+        last_added: 0,
+        last_batch_id: None,
+    };
+    apply_request(&mut sub, req);
+    validate(&sub, &app_state.config)?;
+
+    let mut subs = app_state.subscriptions.lock().unwrap();
+    sub.id = subs.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+    subs.push(sub.clone());
+    save_subscriptions(&subs);
+
+    log_info(&format!("🔔 Dodano subskrypcję #{}: {}", sub.id, sub.url));
+    Ok(sub)
+}
+
+/// Edytuje subskrypcję. `Ok(None)`, jeśli nie istnieje.
+pub(crate) fn update(app_state: &AppState, id: u64, req: SubscriptionRequest) -> Result<Option<Subscription>, String> {
+    let mut subs = app_state.subscriptions.lock().unwrap();
+    let Some(sub) = subs.iter_mut().find(|s| s.id == id) else {
+        return Ok(None);
+    };
+
+    let mut updated = sub.clone();
+    apply_request(&mut updated, req);
+    validate(&updated, &app_state.config)?;
+    *sub = updated.clone();
+    save_subscriptions(&subs);
+
+    Ok(Some(updated))
+}
+
+pub(crate) fn remove(app_state: &AppState, id: u64) -> bool {
+    let mut subs = app_state.subscriptions.lock().unwrap();
+    let before = subs.len();
+    subs.retain(|s| s.id != id);
+    if subs.len() == before {
+        return false;
+    }
+    save_subscriptions(&subs);
+    log_info(&format!("🔕 Usunięto subskrypcję #{id}"));
+    true
+}
+
+/// Wpisy archiwum `--download-archive` (`ekstraktor id`), jeśli plik istnieje.
+fn load_archive() -> HashSet<String> {
+    fs::read_to_string(download_archive_path())
+        .map(|data| data.lines().map(|l| l.trim().to_lowercase()).collect())
+        .unwrap_or_default()
+}
+
+/// Usuwa z playlisty pozycje zapisane w archiwum pobranych. Pozostawia puste miejsca,
+/// żeby numeracja pozycji się nie przesunęła.
+fn skip_archived(playlist: &mut FlatPlaylist, archive: &HashSet<String>) -> usize {
+    let mut skipped = 0;
+    for slot in playlist.entries.iter_mut() {
+        let archived = slot.as_ref().is_some_and(|entry| match (&entry.ie_key, &entry.id) {
+            (Some(ie), Some(id)) => archive.contains(&format!("{ie} {id}").to_lowercase()),
+            _ => false,
+        });
+        if archived {
+            *slot = None;
+            skipped += 1;
+        }
+    }
+    skipped
+}
+
+/// Sprawdza subskrypcję i dodaje do kolejki nowe pozycje (pomijając te z historii,
+/// kolejki i archiwum pobranych). Zwraca zaktualizowaną subskrypcję.
+pub(crate) async fn check(app_state: &web::Data<AppState>, id: u64) -> Option<Subscription> {
+    let sub = get(app_state, id)?;
+    log_info(&format!("🔔 Sprawdzam subskrypcję #{id}: {}", sub.url));
+
+    let url = sub.url.clone();
+    let items = sub.playlist_items.clone();
+    let username = sub.username.clone();
+    let password = sub.password.clone();
+    let expanded = tokio::task::spawn_blocking(move || {
+        expand_playlist(&url, items.as_deref(), username.as_deref(), password.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Błąd sprawdzania subskrypcji: {e}"))
+    .and_then(|res| res.map_err(|(_, msg)| msg))
+    .and_then(|playlist| match playlist.kind.as_deref() {
+        Some("playlist") => Ok(playlist),
+        _ => Err("Adres nie wskazuje playlisty ani kanału".to_string()),
+    });
+
+    let outcome = expanded.map(|mut playlist| {
+        let archived = skip_archived(&mut playlist, &load_archive());
+        let mut output_path = PathBuf::from(downloads_folder());
+        if !sub.subfolder.is_empty() {
+            output_path.push(&sub.subfolder);
+        }
+        if let Err(e) = fs::create_dir_all(&output_path) {
+            log_error(&format!("📂 Nie udało się utworzyć folderu {:?}: {e}", output_path));
+        }
+
+        let template = DownloadQueueItem {
+            url: sub.url.clone(),
+            quality: sub.quality.clone(),
+            format_selector: sub.format_selector.clone(),
+            subfolder: sub.subfolder.clone(),
+            title: None,
+            username: sub.username.clone(),
+            password: sub.password.clone(),
+            id: 0,
+            attempt: 0,
+            last_error: None,
+            retry_at: None,
+            use_archive: None,
+            batch_id: None,
+            playlist_index: None,
+            start_after: None,
+            limit_rate: None,
+            title_from_metadata: false,
+            thumbnail: None,
+            // szablon mógł zniknąć z konfiguracji - wtedy obowiązuje domyślny
+            filename_template: sub
+                .filename_template
+                .as_deref()
+                .and_then(|t| filename_template::resolve(&app_state.config, Some(t)).ok()),
+            on_collision: None,
+            subtitles: None,
+        };
+        // duplikaty to pozycje już pobrane albo czekające - nie są błędem
+        let title = playlist.title.clone();
+        let result = enqueue_playlist(app_state, template, playlist, false);
+        log_info(&format!(
+            "🔔 Subskrypcja #{id}: {} nowych pozycji, {archived} w archiwum",
+            result.added
+        ));
+        (title, result.added, result.batch_id)
+    });
+
+    let mut subs = app_state.subscriptions.lock().unwrap();
+    let sub = subs.iter_mut().find(|s| s.id == id)?;
+    sub.last_checked = Some(current_unix_time_f64());
+    match outcome {
+        Ok((title, added, batch_id)) => {
+            sub.last_error = None;
+            sub.last_added = added;
+            if batch_id.is_some() {
+                sub.last_batch_id = batch_id;
+            }
+            if sub.title.is_none() {
+                sub.title = title;
+            }
+        }
+        Err(e) => {
+            log_error(&format!("❌ Subskrypcja #{id}: {e}"));
+            sub.last_error = Some(e);
+            sub.last_added = 0;
+        }
+    }
+    let updated = sub.clone();
+    save_subscriptions(&subs);
+    Some(updated)
+}
+
+/// Pętla w tle - co minutę sprawdza włączone subskrypcje, którym minął odstęp.
+pub(crate) async fn poll_loop(app_state: web::Data<AppState>) {
+    loop {
+        let now = current_unix_time_f64();
+        let due: Vec<u64> = list(&app_state)
+            .into_iter()
+            .filter(|s| s.enabled)
+            .filter(|s| s.last_checked.is_none_or(|t| now - t >= (s.interval_minutes * 60) as f64))
+            .map(|s| s.id)
+            .collect();
+
+        for id in due {
+            check(&app_state, id).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(POLL_SECS)).await;
+    }
+}
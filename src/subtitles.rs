@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::ServerConfig;
+use crate::models::{SubtitleFormat, SubtitleMode, SubtitleOptions, SubtitleTrack, YtDlpSubtitle};
+use crate::{log_error, log_info};
+
+/// Rozszerzenia plików napisów, które yt-dlp może zapisać obok filmu.
+const SUBTITLE_EXTS: [&str; 7] = ["srt", "vtt", "ass", "ssa", "ttml", "srv3", "json3"];
+
+/// Ustawienia napisów zadania uzupełnione wartościami serwera.
+pub(crate) struct SubtitleSettings {
+    pub(crate) languages: Vec<String>,
+    pub(crate) manual: bool,
+    pub(crate) automatic: bool,
+    pub(crate) format: SubtitleFormat,
+    pub(crate) mode: SubtitleMode,
+}
+
+/// Sprawdza kody języków z żądania. Dozwolona jest składnia `--sub-langs` yt-dlp
+/// (`en.*`, `all`, `-live_chat`), ale nie przecinki - te rozdzielają pozycje listy.
+pub(crate) fn validate(options: &SubtitleOptions) -> Result<(), String> {
+    for lang in options.languages.iter().flatten() {
+        let lang = lang.trim();
+        if lang.is_empty()
+            || lang.len() > 32
+            || !lang.chars().all(|c| c.is_ascii_alphanumeric() || "-_.*+?^$|()[]".contains(c))
+        {
+            return Err(format!("Nieprawidłowy kod języka napisów: {lang}"));
+        }
+    }
+    Ok(())
+}
+
+/// Łączy napisy z żądania z ustawieniami serwera. `None` - zadanie nie pobiera napisów.
+pub(crate) fn settings(options: Option<&SubtitleOptions>, config: &ServerConfig) -> Option<SubtitleSettings> {
+    let options = options.cloned().unwrap_or_default();
+    let settings = SubtitleSettings {
+        languages: options
+            .languages
+            .map(|langs| langs.iter().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect())
+            .unwrap_or_else(|| config.subtitle_languages.clone()),
+        manual: options.manual.unwrap_or(config.subtitle_manual),
+        automatic: options.automatic.unwrap_or(config.subtitle_automatic),
+        format: options.format.unwrap_or(config.subtitle_format),
+        mode: options.mode.unwrap_or(config.subtitle_mode),
+    };
+    if settings.languages.is_empty() || !(settings.manual || settings.automatic) {
+        return None;
+    }
+    Some(settings)
+}
+
+/// Argumenty yt-dlp dla napisów. Konwersja i osadzanie wymagają ffmpeg, a MP3 nie ma
+/// ścieżki napisów - wtedy napisy zostają jako osobne pliki.
+pub(crate) fn ytdlp_args(settings: &SubtitleSettings, target_format: &str, ffmpeg: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if settings.manual {
+        args.push("--write-subs".to_string());
+    }
+    if settings.automatic {
+        args.push("--write-auto-subs".to_string());
+    }
+    args.push("--sub-langs".into());
+    args.push(settings.languages.join(","));
+
+    // format z serwisu, jeśli jest, oszczędza konwersji
+    let format = settings.format.as_str();
+    args.push("--sub-format".into());
+    args.push(format!("{format}/best"));
+    if !ffmpeg {
+        log_error("⚠️ FFmpeg nie jest dostępny - napisy zostaną zapisane bez konwersji, jako osobne pliki");
+        return args;
+    }
+    args.push("--convert-subs".into());
+    args.push(format.into());
+
+    match settings.mode {
+        SubtitleMode::Embed if target_format == "mp3" => {
+            log_info("ℹ️ Plik MP3 nie obsługuje napisów - zostaną zapisane jako osobne pliki");
+        }
+        SubtitleMode::Embed => args.push("--embed-subs".into()),
+        SubtitleMode::Sidecar => {}
+    }
+    args
+}
+
+/// Argumenty ffmpeg zachowujące osadzone napisy przy konwersji MP4 do MKV/WebM
+/// (`mov_text` z MP4 nie da się skopiować bez zmiany kodeka).
+pub(crate) fn ffmpeg_args(target_format: &str) -> Vec<String> {
+    let codec = if target_format == "webm" { "webvtt" } else { "srt" };
+    ["-map", "0:v", "-map", "0:a?", "-map", "0:s?", "-c:s", codec]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Przenosi pliki napisów filmu (`Film.pl.srt`) z folderu roboczego obok pliku docelowego,
+/// nadając im jego nazwę. Zwraca ścieżki przeniesionych plików.
+pub(crate) fn move_sidecars(video: &Path, target: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem), Some(target_stem)) = (video.parent(), video.file_stem(), target.file_stem()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let target_stem = target_stem.to_string_lossy();
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut moved = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let is_subtitle = path
+            .extension()
+            .is_some_and(|ext| SUBTITLE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str()));
+        if path == video || !is_subtitle || !name.starts_with(&prefix) {
+            continue;
+        }
+
+        // napisy należą do filmu pod `target`, więc stare pliki o tej nazwie są zastępowane
+        let destination = target.with_file_name(format!("{target_stem}{}", &name[prefix.len() - 1..]));
+        let _ = fs::remove_file(&destination);
+        match fs::rename(&path, &destination) {
+            Ok(()) => {
+                log_info(&format!("💬 Zapisano napisy: {}", destination.to_string_lossy()));
+                moved.push(destination);
+            }
+            Err(e) => log_error(&format!("Nie udało się przenieść napisów {:?}: {e}", path)),
+        }
+    }
+    moved
+}
+
+/// Lista dostępnych napisów z wyniku `yt-dlp -J`: najpierw dodane przez autora, potem automatyczne.
+pub(crate) fn tracks(
+    manual: HashMap<String, Vec<YtDlpSubtitle>>,
+    automatic: HashMap<String, Vec<YtDlpSubtitle>>,
+) -> Vec<SubtitleTrack> {
+    let to_tracks = |subs: HashMap<String, Vec<YtDlpSubtitle>>, automatic: bool| {
+        let mut tracks: Vec<SubtitleTrack> = subs
+            .into_iter()
+            // czat transmisji na żywo YouTube też trafia do napisów
+            .filter(|(lang, _)| lang != "live_chat")
+            .map(|(language, variants)| {
+                let mut formats: Vec<String> = variants.iter().filter_map(|v| v.ext.clone()).collect();
+                formats.dedup();
+                SubtitleTrack {
+                    language,
+                    name: variants.into_iter().find_map(|v| v.name),
+                    automatic,
+                    formats,
+                }
+            })
+            .collect();
+        tracks.sort_by(|a, b| a.language.cmp(&b.language));
+        tracks
+    };
+
+    let mut tracks = to_tracks(manual, false);
+    tracks.extend(to_tracks(automatic, true));
+    tracks
+}